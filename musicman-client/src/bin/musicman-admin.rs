@@ -0,0 +1,174 @@
+//! Operator tool for a running musicman server.
+//!
+//!     MUSICMAN_ADMIN_TOKEN=... musicman-admin [addr] <command>
+
+// shared with the client, for its TLS settings and known servers
+#[allow(dead_code)]
+#[path = "../config.rs"]
+mod config;
+#[path = "../tls.rs"]
+mod tls;
+
+use anyhow::{Result, anyhow};
+use colored::Colorize;
+use musicman_protocols::*;
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    os::unix::net::UnixStream,
+    process::exit,
+};
+use tabled::{Table, Tabled, settings::Style};
+
+#[derive(Tabled)]
+struct ClientRow {
+    #[tabled(rename = "Address")]
+    addr: String,
+
+    #[tabled(rename = "Name")]
+    name: String,
+
+    #[tabled(rename = "Session")]
+    session: String,
+
+    #[tabled(rename = "Track")]
+    track: String,
+
+    #[tabled(rename = "Connected")]
+    connected: String,
+}
+
+trait ReadWrite: Read + Write {}
+impl<T: Read + Write> ReadWrite for T {}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let addr = match args.first() {
+        Some(a) if a.contains(':') => args.remove(0),
+        _ => "0.0.0.0:4000".to_string(),
+    };
+
+    let request = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["rescan"] => AdminRequest::Rescan { full: false },
+        ["rescan", "--full"] => AdminRequest::Rescan { full: true },
+        ["stats"] => AdminRequest::Stats,
+        ["clients"] => AdminRequest::Clients,
+        ["kick", addr] => AdminRequest::Disconnect {
+            addr: addr.to_string(),
+        },
+        _ => {
+            print_usage();
+            exit(2);
+        }
+    };
+
+    let Ok(token) = std::env::var("MUSICMAN_ADMIN_TOKEN") else {
+        eprintln!(
+            "{}",
+            "Set MUSICMAN_ADMIN_TOKEN to the server's admin token.".red()
+        );
+        exit(2);
+    };
+
+    if let Err(e) = run(&addr, token, request) {
+        eprintln!("{} {e}", "Error:".red());
+        exit(1);
+    }
+}
+
+fn run(addr: &str, token: String, request: AdminRequest) -> Result<()> {
+    let tls = config::load_config().tls;
+    let mut stream: Box<dyn ReadWrite> = match addr.strip_prefix("unix:") {
+        Some(path) => Box::new(UnixStream::connect(path)?),
+        None if tls.enabled => Box::new(tls::connect(addr, &tls)?),
+        None => Box::new(TcpStream::connect(addr)?),
+    };
+    send(&mut stream, &Request::Admin { token, request })?;
+
+    loop {
+        let res = match read(&mut stream)? {
+            Response::Admin(res) => res,
+            Response::Error { message } => return Err(anyhow!(message)),
+            // anything else is meant for regular clients
+            _ => continue,
+        };
+
+        match res {
+            AdminResponse::Progress { done, total } => {
+                print!("\rScanning {done}/{total}");
+                std::io::stdout().flush()?;
+                continue;
+            }
+            AdminResponse::Rescanned {
+                songs,
+                added,
+                removed,
+            } => println!("\n{songs} songs, {added} added, {removed} removed."),
+            AdminResponse::Stats(stats) => {
+                println!("{:<14}{}", "Songs:".yellow(), stats.songs);
+                println!("{:<14}{}", "Uptime:".yellow(), duration(stats.uptime));
+                println!("{:<14}{}", "Clients:".yellow(), stats.clients);
+                println!("{:<14}{}", "Streams:".yellow(), stats.active_streams);
+                println!("{:<14}{}", "Sent:".yellow(), bytes(stats.bytes_sent));
+            }
+            AdminResponse::Clients(clients) => {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0);
+                let rows = clients.into_iter().map(|c| ClientRow {
+                    addr: c.addr,
+                    name: c.name.unwrap_or_default(),
+                    session: c.session.unwrap_or_default(),
+                    track: c.track.map(|(_, title)| title).unwrap_or_default(),
+                    connected: duration(now.saturating_sub(c.connected)),
+                });
+                println!("{}", Table::new(rows).with(Style::rounded()));
+            }
+            AdminResponse::Disconnected { addr } => println!("Disconnected {addr}."),
+        }
+        return Ok(());
+    }
+}
+
+fn duration(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m{}s", secs / 60, secs % 60),
+        _ => format!("{}h{}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+fn bytes(n: u64) -> String {
+    match n {
+        0..1024 => format!("{n} B"),
+        1024..1_048_576 => format!("{:.1} KiB", n as f64 / 1024.0),
+        1_048_576..1_073_741_824 => format!("{:.1} MiB", n as f64 / 1_048_576.0),
+        _ => format!("{:.1} GiB", n as f64 / 1_073_741_824.0),
+    }
+}
+
+fn send(stream: &mut impl Write, req: &Request) -> Result<()> {
+    let bytes = bincode::serialize(req)?;
+    stream.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    stream.write_all(&bytes)?;
+    Ok(())
+}
+
+fn read(stream: &mut impl Read) -> Result<Response> {
+    let mut len_buf = [0u8; 4];
+    stream.read_exact(&mut len_buf)?;
+    let mut buf = vec![0u8; u32::from_be_bytes(len_buf) as usize];
+    stream.read_exact(&mut buf)?;
+    Ok(bincode::deserialize(&buf)?)
+}
+
+fn print_usage() {
+    eprintln!(
+        "Usage: {} [{}] <{}>",
+        "musicman-admin".blue().bold(),
+        "addr".purple(),
+        "rescan [--full]|stats|clients|kick <addr>".yellow()
+    );
+    eprintln!("The admin token is read from MUSICMAN_ADMIN_TOKEN.");
+}
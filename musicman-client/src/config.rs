@@ -0,0 +1,112 @@
+use musicman_protocols::Credentials;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Settings read from `client.toml` in the musicman config directory. Every
+/// field is optional.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    /// Session to attach to on startup, instead of the last one used.
+    pub session: Option<String>,
+    /// Name other clients can control this one by. Defaults to the host name.
+    pub name: Option<String>,
+    pub scrobble: ScrobbleConfig,
+    pub login: LoginConfig,
+    pub tls: TlsConfig,
+}
+
+/// Connect over TLS. The server's certificate is trusted the first time and
+/// has to stay the same after that, unless `fingerprint` pins it.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    /// SHA-256 of the certificate, as `openssl x509 -fingerprint -sha256`
+    /// prints it.
+    pub fingerprint: Option<String>,
+}
+
+/// What to log in with when the server wants it: a `token`, or `user` and
+/// `password`.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct LoginConfig {
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub token: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ScrobbleConfig {
+    pub enabled: bool,
+    pub format: ScrobbleFormat,
+    /// Defaults to `listens.jsonl` or `.scrobbler.log` in the config directory.
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrobbleFormat {
+    #[default]
+    ListenBrainz,
+    Scrobbler,
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("musicman"))
+}
+
+/// Loads the config, falling back to the defaults if it's missing or broken.
+pub fn load_config() -> Config {
+    let Some(path) = config_dir().map(|d| d.join("client.toml")) else {
+        return Config::default();
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(data) => toml::from_str(&data).unwrap_or_else(|e| {
+            eprintln!("Ignoring {}: {e}", path.display());
+            Config::default()
+        }),
+        Err(_) => Config::default(),
+    }
+}
+
+fn last_session_file() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("last_session"))
+}
+
+/// The session the client was last attached to, so it can resume it.
+pub fn last_session() -> Option<String> {
+    let name = std::fs::read_to_string(last_session_file()?).ok()?;
+    Some(name.trim().to_string()).filter(|n| !n.is_empty())
+}
+
+pub fn save_last_session(name: Option<&str>) {
+    if let Some(path) = last_session_file() {
+        let _ = std::fs::write(path, name.unwrap_or_default());
+    }
+}
+
+/// The name to register with the server, from the config or else the host
+/// name.
+pub fn client_name(config: &Config) -> Option<String> {
+    let name = match &config.name {
+        Some(name) => name.clone(),
+        None => std::fs::read_to_string("/etc/hostname").ok()?,
+    };
+    Some(name.trim().to_string()).filter(|n| !n.is_empty())
+}
+
+/// The credentials to log in with on connect, if any are configured.
+pub fn credentials(config: &Config) -> Option<Credentials> {
+    let login = &config.login;
+    match (&login.token, &login.user, &login.password) {
+        (Some(token), _, _) => Some(Credentials::Token(token.clone())),
+        (None, Some(user), Some(password)) => Some(Credentials::Password {
+            user: user.clone(),
+            password: password.clone(),
+        }),
+        _ => None,
+    }
+}
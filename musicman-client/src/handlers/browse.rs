@@ -0,0 +1,22 @@
+use crate::{helpers, types::Stream};
+use colored::Colorize;
+use musicman_protocols::{BrowseRequest, Request};
+
+pub fn handle_browse(stream: &Stream, input: Vec<String>) {
+    let arg = input[1..].join(" ");
+    let req = match input[0].as_str() {
+        "artists" => BrowseRequest::Artists,
+        "genres" => BrowseRequest::Genres,
+        "years" => BrowseRequest::Years,
+        "albums" if !arg.is_empty() => BrowseRequest::Albums { artist: arg },
+        "album" if !arg.is_empty() => BrowseRequest::Album { name: arg },
+        cmd => {
+            println!(
+                "{}",
+                format!("Usage: {} <{}>", cmd.blue().bold(), "name".purple()).red()
+            );
+            return;
+        }
+    };
+    helpers::send_to_server(stream, Request::Browse(req));
+}
@@ -0,0 +1,69 @@
+use crate::{helpers, types::*};
+use colored::Colorize;
+use musicman_protocols::Request;
+
+pub fn handle_crossfade(stream: &Stream, player_state: &PlayerState, input: Vec<String>) {
+    let Some(arg) = input.get(1) else {
+        let crossfade = player_state.lock().unwrap().crossfade;
+        match crossfade {
+            Some(xf) => println!(
+                "{} {}s ({})",
+                "Crossfade:".yellow(),
+                xf.secs.to_string().blue(),
+                xf.curve.name().blue()
+            ),
+            None => println!("{}", "Crossfade is off.".yellow()),
+        }
+        return;
+    };
+
+    if arg == "off" || arg == "0" {
+        player_state.lock().unwrap().crossfade = None;
+        hold_back(stream, None);
+        println!("{}", "Crossfade disabled.".green());
+        return;
+    }
+
+    let secs = arg.parse::<f32>().ok().filter(|s| *s > 0.0 && *s <= 30.0);
+    let curve = match input.get(2) {
+        Some(name) => FadeCurve::from_name(name),
+        None => Some(FadeCurve::EqualPower),
+    };
+
+    let (Some(secs), Some(curve)) = (secs, curve) else {
+        println!(
+            "{}",
+            format!(
+                "Usage: {} <{}|off> [{}]",
+                "crossfade".blue().bold(),
+                "seconds".yellow(),
+                "curve".purple()
+            )
+            .red()
+        );
+        println!(
+            "{}",
+            "curve =>
+  linear [constant rate fade]
+  equal  [equal-power fade, the default]
+  scurve [slow start and end]
+"
+            .yellow()
+        );
+        return;
+    };
+
+    player_state.lock().unwrap().crossfade = Some(Crossfade { secs, curve });
+    hold_back(stream, Some(secs));
+    println!(
+        "{}",
+        format!("Crossfading {}s ({}).", secs, curve.name()).green()
+    );
+}
+
+/// Crossfading holds the end of each track back from the sink, so the server
+/// has to stream that much further ahead.
+pub fn hold_back(stream: &Stream, crossfade: Option<f32>) {
+    let secs = crossfade.map_or(0, |secs| secs.ceil() as u32);
+    helpers::send_to_server(stream, Request::HoldBack { secs });
+}
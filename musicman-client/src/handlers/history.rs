@@ -0,0 +1,62 @@
+use crate::{
+    helpers,
+    types::{ClientState, Stream},
+};
+use colored::Colorize;
+use musicman_protocols::{HistoryRequest, Request, parse_age};
+
+pub fn handle_history(stream: &Stream, input: Vec<String>) {
+    let limit = match input.get(1).map(|n| n.parse::<usize>()) {
+        None => 20,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            println!(
+                "{}",
+                format!("Usage: {} [{}]", "history".blue().bold(), "count".purple()).red()
+            );
+            return;
+        }
+    };
+    helpers::send_to_server(stream, Request::History(HistoryRequest::Recent { limit }));
+}
+
+pub fn handle_stats(stream: &Stream, input: Vec<String>, state: &ClientState) {
+    let within = match input.get(2) {
+        None => None,
+        Some(window) => match parse_age(window) {
+            Some(secs) => Some(secs),
+            None => return print_stats_usage(),
+        },
+    };
+
+    let req = match input.get(1).map(String::as_str).unwrap_or("tracks") {
+        "tracks" | "t" => HistoryRequest::TopTracks { within, limit: 20 },
+        "artists" | "a" => HistoryRequest::TopArtists { within, limit: 20 },
+        "song" | "s" => {
+            let Some(song) = state.lock().unwrap().current_song.clone() else {
+                println!("{}", "Nothing is playing.".yellow());
+                return;
+            };
+            HistoryRequest::PlayCount { track_id: song.id }
+        }
+        _ => return print_stats_usage(),
+    };
+    helpers::send_to_server(stream, Request::History(req));
+}
+
+fn print_stats_usage() {
+    println!(
+        "{}",
+        format!(
+            "Usage: {} [{}] [{}]",
+            "stats".blue().bold(),
+            "tracks|artists|song".yellow(),
+            "window".purple()
+        )
+        .red()
+    );
+    println!(
+        "{}",
+        "window => how far back to count, e.g. 7d, 4w, 1y (default: all time)".yellow()
+    );
+}
@@ -1,13 +1,24 @@
 use crate::{helpers, types::*};
 use colored::Colorize;
 use musicman_protocols::*;
-use std::net::TcpStream;
 
+mod browse;
+mod crossfade;
+mod history;
 mod next_prev;
 mod playlist;
+mod radio;
+mod remote;
+mod session;
 mod show;
+pub use browse::*;
+pub use crossfade::*;
+pub use history::*;
 pub use next_prev::*;
 pub use playlist::*;
+pub use radio::*;
+pub use remote::*;
+pub use session::*;
 pub use show::*;
 
 pub fn print_help() {
@@ -15,26 +26,45 @@ pub fn print_help() {
     println!("  {}", "clear        => Clear queue. ".blue());
     println!("  {}", "show, ls     => Show Queue.".blue());
     println!("  {}", "search       => Search index. ".blue());
+    println!("  {}", "artists      => List artists.".blue());
+    println!("  {}", "albums       => List an artist's albums.".blue());
+    println!("  {}", "album        => Pick tracks from an album.".blue());
+    println!("  {}", "genres       => List genres.".blue());
+    println!("  {}", "years        => List release years.".blue());
+    println!("  {}", "history      => Show recently played songs.".blue());
+    println!(
+        "  {}",
+        "stats        => Show most played tracks and artists.".blue()
+    );
     println!("  {}", "replay       => Replay current song.".blue());
     println!("  {}", "pause, p     => Toggle pause/play.".blue());
     println!("  {}", "next         => Goto next song.".blue());
     println!("  {}", "prev         => Goto previous song.".blue());
     println!("  {}", "playlist, pl => Playlist management.".blue());
+    println!(
+        "  {}",
+        "radio        => Keep the queue going with related songs.".blue()
+    );
+    println!(
+        "  {}",
+        "crossfade    => Set crossfade between tracks.".blue()
+    );
+    println!("  {}", "volume       => Set the volume.".blue());
+    println!(
+        "  {}",
+        "session      => Keep the queue on the server.".blue()
+    );
+    println!("  {}", "remote       => Control another client.".blue());
     println!("  {}", "exit         => Exit the player.".blue());
 }
 
-pub fn handle_replay(stream: &TcpStream, state: &ClientState) {
+pub fn handle_replay(stream: &Stream, state: &ClientState) {
     let song = state.lock().unwrap().current_song.clone();
-    if song.is_none() {
-        println!("{}", "No song to replay".red());
+    if let Some(song) = song {
+        helpers::send_to_server(stream, Request::Play { track_id: song.id });
+        println!("{} {}", "Replaying:".yellow(), song.title.blue());
     } else {
-        helpers::send_to_server(
-            &stream,
-            Request::Play {
-                track_id: song.clone().unwrap().id,
-            },
-        );
-        println!("{} {}", "Replaying:".yellow(), song.unwrap().title.blue());
+        println!("{}", "No song to replay".red());
     };
 }
 
@@ -50,29 +80,72 @@ pub fn handle_pause(sink: &RodioSink) {
     }
 }
 
-pub fn handle_clear(state: &ClientState, sink: &RodioSink) {
+pub fn handle_volume(sink: &RodioSink, input: Vec<String>) {
+    let sink = sink.lock().unwrap();
+    match input.get(1).map(|v| v.parse::<u8>()) {
+        None => println!(
+            "{} {}%",
+            "Volume:".yellow(),
+            (sink.volume() * 100.0).round()
+        ),
+        Some(Ok(percent)) if percent <= 100 => {
+            sink.set_volume(percent as f32 / 100.0);
+            println!("{} {percent}%", "Volume set to".green());
+        }
+        Some(_) => println!(
+            "{}",
+            format!("Usage: {} [{}]", "volume".blue().bold(), "0-100".yellow()).red()
+        ),
+    }
+}
+
+pub fn handle_clear(
+    stream: &Stream,
+    state: &ClientState,
+    player_state: &PlayerState,
+    sink: &RodioSink,
+) {
     println!("{}", "Clearing queue...".green());
     if let Ok(mut s) = state.lock() {
         s.queue.clear();
         s.current_song = None;
     }
+    if let Ok(mut ps) = player_state.lock() {
+        // a synced session's streams aren't this client's to stop
+        if ps.sync.is_none() {
+            let next = ps.next.as_ref().and_then(|next| next.stream_id);
+            for stream_id in [ps.stream_id, next].into_iter().flatten() {
+                helpers::send_to_server(stream, Request::Stop { stream_id });
+            }
+        }
+        ps.next = None;
+        ps.tail.samples.clear();
+    }
     sink.lock().unwrap().clear();
 }
 
-pub fn handle_search(stream: &TcpStream, input: Vec<String>) {
+pub fn handle_search(stream: &Stream, input: Vec<String>) {
     if input.len() >= 2 {
         match input[1].as_str() {
             "artist" | "a" => {
                 let artist = input[2..].join(" ").to_lowercase();
-                helpers::send_to_server(&stream, Request::Search(SearchType::ByArtist(artist)));
+                helpers::send_to_server(stream, Request::Search(SearchType::ByArtist(artist)));
                 return;
             }
             "title" | "t" => {
                 let title = input[2..].join(" ").to_lowercase();
-                helpers::send_to_server(&stream, Request::Search(SearchType::ByTitle(title)));
+                helpers::send_to_server(stream, Request::Search(SearchType::ByTitle(title)));
+                return;
+            }
+            _ => {
+                let expr = input[1..].join(" ");
+                if let Err(e) = expr.parse::<Query>() {
+                    println!("{} {}", "Invalid search:".red(), e.to_string().red());
+                    return;
+                }
+                helpers::send_to_server(stream, Request::Search(SearchType::Query(expr)));
                 return;
             }
-            _ => (),
         }
     }
     println!(
@@ -90,6 +163,12 @@ pub fn handle_search(stream: &TcpStream, input: Vec<String>) {
         "selector =>
   a | artist [search by artist]
   t | title  [search by title]
+  (none)     [search everything, with filters]
+
+query => words and field:value filters, all must match
+  radiohead album:\"ok computer\" year:1995..2000 -live duration:>5m
+  fields: title, artist, album, genre, year, duration, plays, added, played
+  -word or -field:value excludes matches
 "
         .yellow()
     );
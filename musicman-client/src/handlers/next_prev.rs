@@ -1,9 +1,8 @@
 use crate::{helpers, player, types::*};
 use colored::Colorize;
 use musicman_protocols::Request;
-use std::net::TcpStream;
 
-pub fn handle_next_prev(stream: &TcpStream, state: &ClientState, input: Vec<String>) {
+pub fn handle_next_prev(stream: &Stream, state: &ClientState, input: Vec<String>) {
     let is_next = input[0] == "next";
 
     let n = match input.get(1) {
@@ -21,9 +20,9 @@ pub fn handle_next_prev(stream: &TcpStream, state: &ClientState, input: Vec<Stri
     };
 
     let result = if is_next {
-        player::get_next_song(&state, n)
+        player::get_next_song(state, n)
     } else {
-        player::get_prev_song(&state, n)
+        player::get_prev_song(state, n)
     };
 
     if result == GetReturn::QueueEmpty {
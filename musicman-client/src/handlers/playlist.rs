@@ -1,39 +1,139 @@
-use crate::{helpers, types::ClientState};
+use crate::{
+    helpers,
+    types::{ClientState, Stream},
+};
 use colored::Colorize;
-use musicman_protocols::{PlaylistRequest, Request};
-use std::net::TcpStream;
-
-pub fn handle_playlist(stream: &TcpStream, input: Vec<String>, state: &ClientState) {
-    if input.len() >= 2 {
-        match input[1].as_str() {
-            "load" | "new" => {
-                if input.len() >= 3 {
-                    let name = input[2..].join(" ").to_lowercase();
-                    if input[1] == "load" {
-                        println!("{}", format!("Loading playlist: {}", name).green());
-                        helpers::send_to_server(
-                            stream,
-                            Request::Playlist(PlaylistRequest::Get { name }),
-                        );
-                    } else if input[1] == "new" {
-                        println!("{}", format!("Creating new playlist: {}", name).green());
-                        helpers::send_to_server(
-                            stream,
-                            Request::Playlist(PlaylistRequest::Create {
-                                name,
-                                songs: state.lock().unwrap().queue.clone(),
-                            }),
-                        );
+use musicman_protocols::{PlaylistFormat, PlaylistRequest, Query, Request};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+pub fn handle_playlist(stream: &Stream, input: Vec<String>, state: &ClientState) {
+    let op = input.get(1).map(String::as_str).unwrap_or_default();
+    let args = input.get(2..).unwrap_or_default();
+    match (op, take_name(args)) {
+        ("ls" | "show", _) => {
+            helpers::send_to_server(stream, Request::Playlist(PlaylistRequest::List));
+            return;
+        }
+        ("import", _) if !args.is_empty() => {
+            let name = match &args[1..] {
+                [] => None,
+                rest => match take_name(rest) {
+                    Some((name, [])) => Some(name),
+                    _ => {
+                        println!("{}", "Usage: pl import <file> [name]".red());
+                        return;
                     }
+                },
+            };
+            handle_import(stream, &args[0], name);
+            return;
+        }
+        ("load", Some((name, []))) => {
+            println!("{}", format!("Loading playlist: {}", name).green());
+            helpers::send_to_server(stream, Request::Playlist(PlaylistRequest::Get { name }));
+            return;
+        }
+        ("new", Some((name, []))) => {
+            println!("{}", format!("Creating new playlist: {}", name).green());
+            helpers::send_to_server(
+                stream,
+                Request::Playlist(PlaylistRequest::Create {
+                    name,
+                    songs: queue_ids(state),
+                }),
+            );
+            return;
+        }
+        ("smart", Some((name, rule))) if !rule.is_empty() => {
+            let query = rule.join(" ");
+            if let Err(e) = query.parse::<Query>() {
+                println!("{} {}", "Invalid rule:".red(), e.to_string().red());
+                return;
+            }
+            println!("{}", format!("Creating smart playlist: {}", name).green());
+            helpers::send_to_server(
+                stream,
+                Request::Playlist(PlaylistRequest::CreateSmart { name, query }),
+            );
+            return;
+        }
+        ("delete" | "rm", Some((name, []))) => {
+            helpers::send_to_server(stream, Request::Playlist(PlaylistRequest::Delete { name }));
+            return;
+        }
+        ("rename", Some((name, rest))) => {
+            if let Some((new_name, [])) = take_name(rest) {
+                helpers::send_to_server(
+                    stream,
+                    Request::Playlist(PlaylistRequest::Rename { name, new_name }),
+                );
+                return;
+            }
+        }
+        ("share", Some((name, rest))) if rest.len() <= 1 => {
+            let shared = match rest.first().map(String::as_str) {
+                None | Some("on") => true,
+                Some("off") => false,
+                _ => {
+                    println!("{}", "Usage: pl share <name> [on|off]".red());
                     return;
                 }
+            };
+            helpers::send_to_server(
+                stream,
+                Request::Playlist(PlaylistRequest::Share { name, shared }),
+            );
+            return;
+        }
+        ("add", Some((name, []))) => {
+            let songs = queue_ids(state);
+            if songs.is_empty() {
+                println!("{}", "Queue is Empty.".yellow());
+                return;
             }
-            "ls" | "show" => {
-                helpers::send_to_server(stream, Request::Playlist(PlaylistRequest::List));
+            helpers::send_to_server(
+                stream,
+                Request::Playlist(PlaylistRequest::AddSongs { name, songs }),
+            );
+            return;
+        }
+        ("remove", Some((name, positions))) if !positions.is_empty() => {
+            if let Some(indices) = parse_positions(positions) {
+                helpers::send_to_server(
+                    stream,
+                    Request::Playlist(PlaylistRequest::RemoveSongs { name, indices }),
+                );
                 return;
             }
-            _ => (),
         }
+        ("move" | "mv", Some((name, positions))) if positions.len() == 2 => {
+            if let Some(pos) = parse_positions(positions) {
+                helpers::send_to_server(
+                    stream,
+                    Request::Playlist(PlaylistRequest::Move {
+                        name,
+                        from: pos[0],
+                        to: pos[1],
+                    }),
+                );
+                return;
+            }
+        }
+        ("export", Some((name, [file]))) => {
+            let path = PathBuf::from(file);
+            let Some(format) = format_of(&path) else {
+                println!("{}", "Supported formats: m3u, m3u8, pls, xspf".red());
+                return;
+            };
+            state.lock().unwrap().pending_export = Some(path);
+            helpers::send_to_server(
+                stream,
+                Request::Playlist(PlaylistRequest::Export { name, format }),
+            );
+            return;
+        }
+        _ => (),
     }
 
     println!(
@@ -49,10 +149,97 @@ pub fn handle_playlist(stream: &TcpStream, input: Vec<String>, state: &ClientSta
     println!(
         "{}",
         "operation =>
-  load [load a playlist by name]
-  new  [make new playlist with current queue named <name>]
-  show [list available playlists]
+  load   <name>              [load a playlist by name, or user/name for a shared one]
+  new    <name>              [make new playlist with current queue named <name>]
+  smart  <name> <rule...>    [make a playlist of every track matching a rule]
+  show                       [list available playlists]
+  add    <name>              [append the current queue to a playlist]
+  remove <name> <n...>       [remove entries by number]
+  move   <name> <from> <to>  [move an entry to a new position]
+  rename <name> <new_name>   [rename a playlist]
+  delete <name>              [delete a playlist]
+  share  <name> [on|off]     [let other users load a playlist, read-only]
+  import <file> [name]       [import an m3u, m3u8, pls or xspf file]
+  export <name> <file>       [export to a file, format taken from its extension]
+
+name => one word, or several in double quotes, e.g. \"road trip\"
+
+rule => field:value ...  (all must match)
+  title, artist, album, genre   [text contained in the field]
+  year, duration, plays         [number, e.g. year:1990..1999, duration:>5m]
+  added, played                 [time ago, e.g. added:<30d, played:>1y]
 "
         .yellow()
     );
 }
+
+/// Takes a playlist name off the front of `args`: one word, or several in
+/// double quotes. Returns it along with the arguments that follow.
+fn take_name(args: &[String]) -> Option<(String, &[String])> {
+    let first = args.first()?;
+    let Some(opened) = first.strip_prefix('"') else {
+        return Some((first.to_lowercase(), &args[1..]));
+    };
+    let mut words = Vec::new();
+    for (i, word) in std::iter::once(opened)
+        .chain(args[1..].iter().map(String::as_str))
+        .enumerate()
+    {
+        if let Some(word) = word.strip_suffix('"') {
+            words.push(word);
+            let name = words.join(" ").to_lowercase();
+            return (!name.trim().is_empty()).then_some((name, &args[i + 1..]));
+        }
+        words.push(word);
+    }
+    None
+}
+
+/// Parses 1-based entry numbers as shown in the tables into 0-based positions.
+fn parse_positions(args: &[String]) -> Option<Vec<usize>> {
+    args.iter()
+        .map(|a| a.parse::<usize>().ok().filter(|n| *n >= 1).map(|n| n - 1))
+        .collect()
+}
+
+fn queue_ids(state: &ClientState) -> Vec<Uuid> {
+    state.lock().unwrap().queue.iter().map(|s| s.id).collect()
+}
+
+fn handle_import(stream: &Stream, file: &str, name: Option<String>) {
+    let path = Path::new(file);
+    let Some(format) = format_of(path) else {
+        println!("{}", "Supported formats: m3u, m3u8, pls, xspf".red());
+        return;
+    };
+    let contents = match std::fs::read(path) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).to_string(),
+        Err(e) => {
+            println!("{} {}", "Could not read file:".red(), e.to_string().red());
+            return;
+        }
+    };
+    let name = match name {
+        Some(name) => name,
+        None => path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_lowercase())
+            .unwrap_or_default(),
+    };
+
+    println!("{}", format!("Importing playlist: {}", name).green());
+    helpers::send_to_server(
+        stream,
+        Request::Playlist(PlaylistRequest::Import {
+            name,
+            format,
+            contents,
+        }),
+    );
+}
+
+fn format_of(path: &Path) -> Option<PlaylistFormat> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .and_then(PlaylistFormat::from_extension)
+}
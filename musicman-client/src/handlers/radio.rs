@@ -0,0 +1,34 @@
+use crate::types::*;
+use colored::Colorize;
+
+pub fn handle_radio(state: &ClientState, input: Vec<String>) {
+    let mut state = state.lock().unwrap();
+    let enable = match input.get(1).map(String::as_str) {
+        None => !state.radio,
+        Some("on") => true,
+        Some("off") => false,
+        Some(_) => {
+            println!(
+                "{}",
+                format!("Usage: {} [{}]", "radio".blue().bold(), "on|off".yellow()).red()
+            );
+            return;
+        }
+    };
+
+    state.radio = enable;
+    state.radio_pending = false;
+    if !enable {
+        println!("{}", "Radio off.".green());
+    } else if state.queue.is_empty() {
+        println!(
+            "{}",
+            "Radio on. It will pick up once something is queued.".green()
+        );
+    } else {
+        println!(
+            "{}",
+            "Radio on. Related songs will keep the queue going.".green()
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{helpers, types::*};
+use colored::Colorize;
+use musicman_protocols::{Control, Request, SongMeta};
+
+pub fn handle_remote(stream: &Stream, input: Vec<String>, state: &ClientState) {
+    let (target, action) = match (input.get(1), input.get(2)) {
+        (None, _) => {
+            helpers::send_to_server(stream, Request::Clients);
+            return;
+        }
+        (Some(ls), None) if ls == "ls" => {
+            helpers::send_to_server(stream, Request::Clients);
+            return;
+        }
+        (Some(target), Some(action)) => (target.clone(), action.as_str()),
+        _ => return print_usage(),
+    };
+
+    let command = match action {
+        "pause" => Control::Pause,
+        "resume" | "play" => Control::Resume,
+        "next" => Control::Next,
+        "prev" => Control::Prev,
+        "volume" | "vol" => match input.get(3).and_then(|v| v.parse::<u8>().ok()) {
+            Some(percent) if percent <= 100 => Control::SetVolume(percent),
+            _ => return print_usage(),
+        },
+        "enqueue" | "add" => match pick_from_queue(&input[3..], state) {
+            Some(songs) => Control::Enqueue(songs),
+            None => return,
+        },
+        _ => return print_usage(),
+    };
+
+    helpers::send_to_server(stream, Request::Control { target, command });
+}
+
+/// The queue entries numbered in `picks`, as `show` lists them, or the
+/// whole queue without any.
+fn pick_from_queue(picks: &[String], state: &ClientState) -> Option<Vec<SongMeta>> {
+    let queue = state.lock().unwrap().queue.clone();
+    let songs: Vec<_> = match picks.is_empty() {
+        true => queue,
+        false => picks
+            .iter()
+            .filter_map(|p| p.parse::<usize>().ok())
+            .filter_map(|n| queue.get(n.wrapping_sub(1)).cloned())
+            .collect(),
+    };
+    if songs.is_empty() {
+        println!(
+            "{}",
+            "Nothing to send, the picks don't match the queue.".red()
+        );
+        return None;
+    }
+    Some(songs)
+}
+
+fn print_usage() {
+    println!(
+        "{}",
+        format!(
+            "Usage: {} [{}] | {} <{}>",
+            "remote".blue().bold(),
+            "ls".yellow(),
+            "remote <name>".blue().bold(),
+            "pause|resume|next|prev|volume 0-100|enqueue [nums]".yellow()
+        )
+        .red()
+    );
+}
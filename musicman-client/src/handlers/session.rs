@@ -0,0 +1,84 @@
+use crate::{
+    helpers,
+    types::{ClientState, PlayerState, Stream},
+};
+use colored::Colorize;
+use musicman_protocols::{Request, SessionRequest};
+
+pub fn handle_session(
+    stream: &Stream,
+    input: Vec<String>,
+    state: &ClientState,
+    player_state: &PlayerState,
+) {
+    let req = match input.get(1).map(String::as_str) {
+        None => {
+            let synced = player_state.lock().unwrap().sync.is_some();
+            match state.lock().unwrap().session.clone() {
+                Some(name) if synced => {
+                    println!(
+                        "{} {} {}",
+                        "Session:".yellow(),
+                        name.blue(),
+                        "(in sync)".green()
+                    )
+                }
+                Some(name) => println!("{} {}", "Session:".yellow(), name.blue()),
+                None => println!("{}", "Not attached to a session.".yellow()),
+            }
+            return;
+        }
+        Some("ls" | "show") => SessionRequest::List,
+        Some("off") => SessionRequest::Detach,
+        Some("sync") => match input.get(2).map(String::as_str) {
+            Some("on") | None => {
+                helpers::send_to_server(stream, Request::Session(SessionRequest::Sync(true)));
+                resume_in_sync(stream, state, player_state);
+                return;
+            }
+            Some("off") => SessionRequest::Sync(false),
+            Some(_) => return print_usage(),
+        },
+        Some(name) if input.len() == 2 => SessionRequest::Attach {
+            name: name.to_lowercase(),
+        },
+        Some(_) => return print_usage(),
+    };
+    helpers::send_to_server(stream, Request::Session(req));
+}
+
+/// Hands the song playing here to the session, so everyone in it carries
+/// on from the same spot.
+fn resume_in_sync(stream: &Stream, state: &ClientState, player_state: &PlayerState) {
+    let ps = player_state.lock().unwrap();
+    let Some(song) = state.lock().unwrap().current_song.clone() else {
+        return;
+    };
+    let position = match ps.position_id == Some(song.id) {
+        true => ps.position.as_secs() as u32,
+        false => 0,
+    };
+    helpers::send_to_server(
+        stream,
+        Request::Resume {
+            track_id: song.id,
+            position,
+        },
+    );
+}
+
+fn print_usage() {
+    println!(
+        "{}",
+        format!(
+            "Usage: {} [{}|{}|{}|{} {}]",
+            "session".blue().bold(),
+            "name".purple(),
+            "ls".yellow(),
+            "off".yellow(),
+            "sync".yellow(),
+            "on|off".purple()
+        )
+        .red()
+    );
+}
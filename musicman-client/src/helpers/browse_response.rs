@@ -0,0 +1,36 @@
+use crate::types::*;
+use musicman_protocols::*;
+use std::sync::mpsc;
+use tabled::settings::{Style, object::Cell};
+
+pub fn handle_browse_response(bres: BrowseResponse, utx: &mpsc::Sender<UiRequest>) {
+    let (heading, groups) = match bres {
+        BrowseResponse::Artists(groups) => ("Artist", groups),
+        BrowseResponse::Albums { artist, albums } => {
+            if albums.is_empty() {
+                utx.send(UiRequest::Display(format!("No albums by {artist}.")))
+                    .unwrap();
+                return;
+            }
+            ("Album", albums)
+        }
+        BrowseResponse::Genres(groups) => ("Genre", groups),
+        BrowseResponse::Years(groups) => ("Year", groups),
+    };
+
+    if groups.is_empty() {
+        utx.send(UiRequest::Display("Nothing to show.".to_string()))
+            .unwrap();
+        return;
+    }
+
+    let table_vec = groups.into_iter().enumerate().map(|(i, g)| GroupTable {
+        id: (i + 1) as u16,
+        name: g.name,
+        tracks: g.tracks,
+    });
+    let mut table = tabled::Table::new(table_vec);
+    table.with(Style::rounded());
+    table.modify(Cell::new(0, 1), heading);
+    utx.send(UiRequest::Display(table.to_string())).unwrap();
+}
@@ -0,0 +1,59 @@
+use crate::{helpers, player, types::*};
+use musicman_protocols::*;
+use std::sync::mpsc;
+
+/// Carries out a command another client sent through the server.
+pub fn handle_control_response(
+    from: String,
+    command: Control,
+    stream: &Stream,
+    state: &ClientState,
+    sink: &RodioSink,
+    utx: &mpsc::Sender<UiRequest>,
+) {
+    let done = match command {
+        Control::Pause => {
+            sink.lock().unwrap().pause();
+            "paused playback".to_string()
+        }
+        Control::Resume => {
+            sink.lock().unwrap().play();
+            "resumed playback".to_string()
+        }
+        Control::Next | Control::Prev => {
+            let result = match command {
+                Control::Next => player::get_next_song(state, 1),
+                _ => player::get_prev_song(state, 1),
+            };
+            let song = state.lock().unwrap().current_song.clone();
+            match (result, song) {
+                (GetReturn::Ok, Some(song)) => {
+                    helpers::send_to_server(stream, Request::Play { track_id: song.id });
+                    format!("skipped to {}", song.title)
+                }
+                _ => "tried to skip, but the queue is empty".to_string(),
+            }
+        }
+        Control::Enqueue(songs) => {
+            let count = songs.len();
+            let mut st = state.lock().unwrap();
+            if st.queue.is_empty()
+                && let Some(first) = songs.first()
+            {
+                st.current_song = Some(first.clone());
+                st.current_idx = 0;
+                helpers::send_to_server(stream, Request::Play { track_id: first.id });
+            }
+            st.queue.extend(songs);
+            format!("added {count} songs to the queue")
+        }
+        Control::SetVolume(percent) => {
+            sink.lock()
+                .unwrap()
+                .set_volume(percent.min(100) as f32 / 100.0);
+            format!("set the volume to {}%", percent.min(100))
+        }
+    };
+    utx.send(UiRequest::Display(format!("{from} {done}.")))
+        .unwrap();
+}
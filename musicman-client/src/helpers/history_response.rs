@@ -0,0 +1,79 @@
+use crate::types::*;
+use musicman_protocols::*;
+use std::{
+    sync::mpsc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tabled::settings::{Style, object::Cell};
+
+pub fn handle_history_response(hres: HistoryResponse, utx: &mpsc::Sender<UiRequest>) {
+    let out = match hres {
+        HistoryResponse::Recent(plays) if plays.is_empty() => "Nothing played yet.".to_string(),
+        HistoryResponse::Recent(plays) => {
+            let table_vec = plays.into_iter().map(|p| HistoryTable {
+                when: ago(p.time),
+                title: p.song.title,
+                artists: p.song.artists.join(", "),
+                client: p.client,
+            });
+            tabled::Table::new(table_vec)
+                .with(Style::rounded())
+                .to_string()
+        }
+        HistoryResponse::TopTracks(top) => plays_table(
+            "Title",
+            top.into_iter().map(|t| {
+                (
+                    format!("{} - {}", t.song.artists.join(", "), t.song.title),
+                    t.plays,
+                )
+            }),
+        ),
+        HistoryResponse::TopArtists(top) => {
+            plays_table("Artist", top.into_iter().map(|a| (a.name, a.plays)))
+        }
+        HistoryResponse::PlayCount {
+            song,
+            plays,
+            last_played,
+        } => match last_played {
+            Some(time) => format!("{} played {plays} times, last {}.", song.title, ago(time)),
+            None => format!("{} hasn't been played yet.", song.title),
+        },
+    };
+    utx.send(UiRequest::Display(out)).unwrap();
+}
+
+fn plays_table(heading: &str, rows: impl Iterator<Item = (String, u32)>) -> String {
+    let table_vec: Vec<PlaysTable> = rows
+        .enumerate()
+        .map(|(i, (name, plays))| PlaysTable {
+            id: (i + 1) as u16,
+            name,
+            plays,
+        })
+        .collect();
+    if table_vec.is_empty() {
+        return "Nothing played yet.".to_string();
+    }
+
+    let mut table = tabled::Table::new(table_vec);
+    table.with(Style::rounded());
+    table.modify(Cell::new(0, 1), heading);
+    table.to_string()
+}
+
+/// "5m ago", "3h ago", "2d ago" and so on.
+fn ago(time: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let secs = now.saturating_sub(time);
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
@@ -1,26 +1,33 @@
+use crate::types::Stream;
 use anyhow::Result;
 use musicman_protocols::*;
-use std::{
-    io::{Read, Write},
-    net::TcpStream,
-};
+use std::io::Read;
 
+mod browse_response;
+mod control_response;
+mod history_response;
 mod playlist_response;
 mod prompt;
 mod search_response;
+mod session_response;
 
+pub use browse_response::*;
+pub use control_response::*;
+pub use history_response::*;
 pub use playlist_response::*;
 pub use prompt::*;
 pub use search_response::*;
+pub use session_response::*;
 
-pub fn send_to_server(mut stream: &TcpStream, req: Request) {
+pub fn send_to_server(stream: &Stream, req: Request) {
     //println!("Sending: {req:?}");
     let req_bytes = bincode::serialize(&req).unwrap();
-    let len = (req_bytes.len() as u32).to_be_bytes();
-    stream.write_all(&len).unwrap();
-    stream.write_all(&req_bytes).unwrap();
+    let mut frame = (req_bytes.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(&req_bytes);
+    // A dropped connection is noticed and picked up again by the reading side.
+    let _ = stream.send(&frame);
 }
-pub fn read_from_client(stream: &mut TcpStream) -> Result<Response> {
+pub fn read_from_client(stream: &mut Stream) -> Result<Response> {
     let mut len_buf = [0u8; 4];
     stream.read_exact(&mut len_buf)?;
     let msg_len = u32::from_be_bytes(len_buf) as usize;
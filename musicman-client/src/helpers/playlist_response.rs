@@ -1,20 +1,20 @@
 use crate::{helpers, types::*};
 use musicman_protocols::*;
-use std::{net::TcpStream, sync::mpsc};
+use std::sync::mpsc;
 use tabled::settings::disable::Remove;
 use tabled::settings::{Style, object::Columns};
 
 pub fn handle_playlist_response(
     plres: PlaylistResponse,
-    stream: &TcpStream,
+    stream: &Stream,
     state: &ClientState,
     utx: &mpsc::Sender<UiRequest>,
     srx: &mpsc::Receiver<UiResponse>,
 ) {
     match plres {
         PlaylistResponse::Playlists(playlists) => {
-            if playlists.len() == 0 {
-                utx.send(UiRequest::Display(format!("No playlists yet.")))
+            if playlists.is_empty() {
+                utx.send(UiRequest::Display("No playlists yet.".to_string()))
                     .unwrap();
                 return;
             }
@@ -24,8 +24,17 @@ pub fn handle_playlist_response(
                 i += 1;
                 PlaylistTable {
                     id: i,
-                    name: pl.name.clone(),
+                    name: match &pl.owner {
+                        Some(owner) => format!("{owner}/{}", pl.name),
+                        None => pl.name.clone(),
+                    },
                     length: pl.len,
+                    rule: pl.query.clone().unwrap_or_default(),
+                    shared: match (&pl.owner, pl.shared) {
+                        (Some(owner), _) => format!("by {owner}"),
+                        (None, true) => "yes".to_string(),
+                        (None, false) => String::new(),
+                    },
                 }
             });
             let out = tabled::Table::new(table_vec)
@@ -33,24 +42,43 @@ pub fn handle_playlist_response(
                 .to_string();
             utx.send(UiRequest::Display(out)).unwrap();
         }
-        PlaylistResponse::Songs(songs) => {
+        PlaylistResponse::Songs {
+            songs,
+            positions,
+            missing,
+        } => {
+            if !missing.is_empty() {
+                let list = missing
+                    .iter()
+                    .map(|(pos, e)| {
+                        format!("  {}. {} - {}", pos + 1, e.artists.join(", "), e.title)
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                utx.send(UiRequest::Display(format!(
+                    "{} entries could not be found:\n{list}",
+                    missing.len()
+                )))
+                .unwrap();
+            }
+
             let len = songs.len();
 
             if len == 0 {
-                utx.send(UiRequest::Display(format!("No results.")))
+                utx.send(UiRequest::Display("No results.".to_string()))
                     .unwrap();
                 return;
             }
 
-            let mut i = 0;
+            // numbered as stored, so `pl remove` and `pl move` match
             let table_vec = songs
                 .iter()
-                .map(|sm| {
-                    i += 1;
+                .zip(&positions)
+                .map(|(sm, pos)| {
                     let mins = sm.duration / 60;
                     let secs = sm.duration % 60;
                     SongTable {
-                        id: i,
+                        id: *pos + 1,
                         title: sm.title.clone(),
                         artists: sm.artists.join(", "),
                         duration: format!("{}m{}s", mins, secs),
@@ -68,24 +96,64 @@ pub fn handle_playlist_response(
             })
             .unwrap();
 
-            if let Ok(s) = srx.recv() {
-                if s.0.starts_with("y") {
-                    if let Ok(mut s) = state.lock() {
-                        s.current_song = Some(songs[0].clone());
-                        s.queue = songs.clone();
-                        s.current_idx = 0;
-                    }
-
-                    utx.send(UiRequest::Display(format!("Added {len} songs to queue.")))
-                        .unwrap();
-                    helpers::send_to_server(
-                        stream,
-                        Request::Play {
-                            track_id: songs[0].id,
-                        },
-                    );
+            if let Ok(s) = srx.recv()
+                && s.0.starts_with("y")
+            {
+                if let Ok(mut s) = state.lock() {
+                    s.current_song = Some(songs[0].clone());
+                    s.queue = songs.clone();
+                    s.current_idx = 0;
                 }
+
+                utx.send(UiRequest::Display(format!("Added {len} songs to queue.")))
+                    .unwrap();
+                helpers::send_to_server(
+                    stream,
+                    Request::Play {
+                        track_id: songs[0].id,
+                    },
+                );
             }
         }
+        PlaylistResponse::Saved(playlist) => {
+            let shared = if playlist.shared { ", shared" } else { "" };
+            utx.send(UiRequest::Display(format!(
+                "Saved playlist {} ({} entries{shared}).",
+                playlist.name, playlist.len
+            )))
+            .unwrap();
+        }
+        PlaylistResponse::Deleted { name } => {
+            utx.send(UiRequest::Display(format!("Deleted playlist {name}.")))
+                .unwrap();
+        }
+        PlaylistResponse::Imported {
+            playlist,
+            unmatched,
+        } => {
+            let mut out = format!(
+                "Imported playlist {} ({} entries).",
+                playlist.name, playlist.len
+            );
+            if !unmatched.is_empty() {
+                out.push_str(&format!(
+                    "\n{} entries could not be matched:\n  {}",
+                    unmatched.len(),
+                    unmatched.join("\n  ")
+                ));
+            }
+            utx.send(UiRequest::Display(out)).unwrap();
+        }
+        PlaylistResponse::Exported { name, contents, .. } => {
+            let path = state.lock().unwrap().pending_export.take();
+            let msg = match path {
+                Some(path) => match std::fs::write(&path, contents) {
+                    Ok(_) => format!("Exported playlist {name} to {}.", path.display()),
+                    Err(e) => format!("Could not write {}: {e}", path.display()),
+                },
+                None => format!("Received an export of {name} nobody asked for."),
+            };
+            utx.send(UiRequest::Display(msg)).unwrap();
+        }
     }
 }
@@ -2,12 +2,12 @@ use crate::{handlers::*, types::*};
 use anyhow::Result;
 use colored::Colorize;
 use reedline::{Reedline, Signal};
-use std::net::TcpStream;
 use std::sync::{Arc, Mutex};
 
 pub fn musicman_prompt(
-    stream: &TcpStream,
+    stream: &Stream,
     state: &Arc<Mutex<ClientStateStruct>>,
+    player_state: &PlayerState,
     sink: &RodioSink,
     editor: &mut Reedline,
 ) -> Result<()> {
@@ -43,11 +43,19 @@ pub fn musicman_prompt(
     match input[0].as_str() {
         "replay" => handle_replay(stream, state),
         "pause" | "p" => handle_pause(sink),
-        "clear" => handle_clear(state, sink),
+        "clear" => handle_clear(stream, state, player_state, sink),
+        "crossfade" | "xf" => handle_crossfade(stream, player_state, input),
         "next" | "prev" => handle_next_prev(stream, state, input),
         "show" | "ls" => handle_show(state),
         "playlist" | "pl" => handle_playlist(stream, input, state),
+        "radio" => handle_radio(state, input),
+        "session" => handle_session(stream, input, state, player_state),
+        "remote" => handle_remote(stream, input, state),
+        "volume" | "vol" => handle_volume(sink, input),
         "search" => handle_search(stream, input),
+        "history" => handle_history(stream, input),
+        "stats" => handle_stats(stream, input, state),
+        "artists" | "albums" | "album" | "genres" | "years" => handle_browse(stream, input),
         "exit" => {
             println!("{}", "Exiting...".green());
             sink.lock().unwrap().stop();
@@ -1,12 +1,12 @@
 use crate::{helpers, types::*};
 use musicman_protocols::*;
-use std::{net::TcpStream, sync::mpsc};
+use std::sync::mpsc;
 use tabled::settings::disable::Remove;
 use tabled::settings::{Style, object::Columns};
 
 pub fn handle_search_response(
     songs: Vec<SongMeta>,
-    stream: &TcpStream,
+    stream: &Stream,
     state: &ClientState,
     utx: &mpsc::Sender<UiRequest>,
     srx: &mpsc::Receiver<UiResponse>,
@@ -32,7 +32,7 @@ pub fn handle_search_response(
             let mins = sm.duration / 60;
             let secs = sm.duration % 60;
             SongTable {
-                id: (i + 1) as u16,
+                id: i + 1,
                 title: sm.title.clone(),
                 artists: sm.artists.join(", "),
                 duration: format!("{}m{}s", mins, secs),
@@ -60,10 +60,11 @@ pub fn handle_search_response(
         } else {
             let mut selected = Vec::new();
             for part in input.split(' ') {
-                if let Ok(idx) = part.trim().parse::<usize>() {
-                    if idx >= 1 && idx <= songs.len() {
-                        selected.push(songs[idx - 1].clone());
-                    }
+                if let Ok(idx) = part.trim().parse::<usize>()
+                    && idx >= 1
+                    && idx <= songs.len()
+                {
+                    selected.push(songs[idx - 1].clone());
                 }
             }
             selected
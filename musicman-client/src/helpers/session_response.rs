@@ -0,0 +1,120 @@
+use crate::{config, handlers, helpers, types::*};
+use musicman_protocols::*;
+use std::{sync::mpsc, time::Duration};
+use tabled::settings::Style;
+
+pub fn handle_session_response(
+    sres: SessionResponse,
+    stream: &Stream,
+    state: &ClientState,
+    player_state: &PlayerState,
+    utx: &mpsc::Sender<UiRequest>,
+) {
+    let out = match sres {
+        SessionResponse::Attached(session) => {
+            config::save_last_session(Some(&session.name));
+            let mut st = state.lock().unwrap();
+            match std::mem::take(&mut st.reattaching) {
+                true => format!("Back in session {}.", session.name),
+                false => {
+                    drop(st);
+                    resume(session, stream, state, player_state)
+                }
+            }
+        }
+        SessionResponse::Detached { name } => {
+            config::save_last_session(None);
+            state.lock().unwrap().session = None;
+            format!("Detached from session {name}.")
+        }
+        SessionResponse::Synced { name, on } => {
+            let mut ps = player_state.lock().unwrap();
+            match on {
+                true => {
+                    // the stream itself starts the clock
+                    if ps.sync.is_none() {
+                        ps.sync = Some(SyncState::new(None));
+                    }
+                    format!("Session {name} now plays in sync.")
+                }
+                false => {
+                    ps.sync = None;
+                    format!("Session {name} stopped playing in sync.")
+                }
+            }
+        }
+        SessionResponse::Sessions(sessions) if sessions.is_empty() => {
+            "No sessions yet.".to_string()
+        }
+        SessionResponse::Sessions(sessions) => {
+            let table_vec = sessions.into_iter().map(|s| SessionTable {
+                name: s.name,
+                len: s.len,
+                clients: s.clients,
+                sync: if s.sync { "on" } else { "" }.to_string(),
+            });
+            tabled::Table::new(table_vec)
+                .with(Style::rounded())
+                .to_string()
+        }
+    };
+    utx.send(UiRequest::Display(out)).unwrap();
+}
+
+/// Takes over the session's queue and modes and carries on from its
+/// position. An empty session keeps the local queue instead, which the
+/// watcher then sends up.
+fn resume(
+    session: SessionState,
+    stream: &Stream,
+    state: &ClientState,
+    player_state: &PlayerState,
+) -> String {
+    let mut ps = player_state.lock().unwrap();
+    let mut st = state.lock().unwrap();
+    st.session = Some(session.name.clone());
+
+    let Some(song) = session.queue.get(session.current_idx).cloned() else {
+        return format!("Attached to session {}.", session.name);
+    };
+
+    st.queue = session.queue;
+    st.current_idx = session.current_idx;
+    st.current_song = Some(song.clone());
+    st.radio = session.modes.radio;
+    st.radio_pending = false;
+    ps.crossfade = session.modes.crossfade.and_then(|(ms, curve)| {
+        FadeCurve::from_name(&curve).map(|curve| Crossfade {
+            secs: ms as f32 / 1000.0,
+            curve,
+        })
+    });
+    handlers::hold_back(stream, ps.crossfade.map(|xf| xf.secs));
+
+    ps.next = None;
+    ps.tail.samples.clear();
+    ps.position = Duration::from_secs(session.position as u64);
+    ps.position_id = Some(song.id);
+    if session.sync {
+        // the server catches this client up with the others
+        ps.sync = Some(SyncState::new(None));
+        return format!("Joined session {} in sync.", session.name);
+    }
+
+    ps.waiting_for_header = true;
+    helpers::send_to_server(
+        stream,
+        Request::Resume {
+            track_id: song.id,
+            position: session.position,
+        },
+    );
+
+    format!(
+        "Resumed session {} at {} ({}m{}s).",
+        session.name,
+        song.title,
+        session.position / 60,
+        session.position % 60
+    )
+}
@@ -1,14 +1,20 @@
+use colored::Colorize;
 use musicman_protocols::*;
 use rodio::{OutputStream, Sink};
-use std::net::TcpStream;
 use std::process::exit;
 use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+mod config;
 mod handlers;
 mod helpers;
+mod mixer;
 mod player;
+mod scrobble;
+mod sync;
 mod threads;
+mod tls;
 mod types;
 use types::*;
 
@@ -22,39 +28,93 @@ fn main() {
         "0.0.0.0:4000".to_string()
     };
 
+    let config = config::load_config();
+
     let (_stream, stream_handle) = OutputStream::try_default().unwrap();
 
     let sink = Arc::new(Mutex::new(Sink::try_new(&stream_handle).unwrap()));
-    let Ok(stream) = TcpStream::connect(&addr) else {
-        println!("Connection Refused");
-        exit(1);
+    let stream = match Stream::connect(&addr, &config.tls) {
+        Ok(stream) => stream,
+        Err(e) => {
+            println!("Could not connect to {addr}: {e}");
+            exit(1);
+        }
     };
 
     let state = Arc::new(Mutex::new(ClientStateStruct {
         queue: Vec::new(),
         current_song: None,
         current_idx: 0,
+        pending_export: None,
+        radio: false,
+        radio_pending: false,
+        session: None,
+        reattaching: false,
     }));
     let player_state = Arc::new(Mutex::new(PlayerStateStruct {
         channels: 2,
         sample_rate: 48000,
         current_id: None,
+        stream_id: None,
+        received: 0,
+        continuing: None,
         waiting_for_header: false,
+        stream_done: false,
+        played_after: 0,
+        plays: 0,
+        next: None,
+        tail: HeldTail::new(2, 48000),
+        crossfade: None,
+        position: Duration::ZERO,
+        position_id: None,
+        sync: None,
     }));
 
     let (stx, srx) = mpsc::channel::<UiResponse>();
     let (utx, urx) = mpsc::channel::<UiRequest>();
     let (ptx, prx) = mpsc::channel::<Response>();
 
-    threads::server_interface(stream.try_clone().unwrap(), state.clone(), ptx, utx, srx);
-    threads::player(prx, sink.clone(), player_state.clone());
+    let scrobbler = if config.scrobble.enabled {
+        match scrobble::Scrobbler::new(&config.scrobble) {
+            Ok(scrobbler) => Some(scrobbler),
+            Err(e) => {
+                let out = format!("Scrobbling disabled: {e}");
+                utx.send(UiRequest::Display(out.red().to_string())).unwrap();
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    threads::server_interface(
+        stream.clone(),
+        state.clone(),
+        player_state.clone(),
+        sink.clone(),
+        ptx,
+        utx.clone(),
+        srx,
+    );
+    threads::player(stream.clone(), prx, sink.clone(), player_state.clone());
     threads::watcher_thread(
-        stream.try_clone().unwrap(),
+        stream.clone(),
         sink.clone(),
         state.clone(),
         player_state.clone(),
+        scrobbler,
+        utx,
     );
-    threads::user_input(stream, state, sink, urx, stx)
+    if let Some(credentials) = config::credentials(&config) {
+        stream.greet(Request::Login(credentials));
+    }
+    if let Some(name) = config::client_name(&config) {
+        stream.greet(Request::Register { name });
+    }
+    if let Some(name) = config.session.or_else(config::last_session) {
+        helpers::send_to_server(&stream, Request::Session(SessionRequest::Attach { name }));
+    }
+    threads::user_input(stream, state, player_state, sink, urx, stx)
         .join()
         .unwrap();
 }
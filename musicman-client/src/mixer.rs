@@ -0,0 +1,157 @@
+use crate::{sync::Synced, types::*};
+use rodio::{Sink, buffer::SamplesBuffer};
+use std::{f32::consts::FRAC_PI_2, mem, sync::Arc};
+
+impl FadeCurve {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" | "lin" => Some(FadeCurve::Linear),
+            "equal" | "equal-power" => Some(FadeCurve::EqualPower),
+            "scurve" | "s-curve" => Some(FadeCurve::SCurve),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FadeCurve::Linear => "linear",
+            FadeCurve::EqualPower => "equal-power",
+            FadeCurve::SCurve => "s-curve",
+        }
+    }
+
+    /// Gain of the incoming track at `t` (0.0 to 1.0) into the fade. The
+    /// outgoing track uses the mirrored value, `gain(1.0 - t)`.
+    pub fn gain(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            FadeCurve::Linear => t,
+            FadeCurve::EqualPower => (t * FRAC_PI_2).sin(),
+            FadeCurve::SCurve => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// Number of samples to keep out of the sink for a crossfade, rounded to
+/// whole frames.
+fn fade_len(crossfade: Option<Crossfade>, channels: u16, sample_rate: u32) -> usize {
+    let Some(crossfade) = crossfade else {
+        return 0;
+    };
+    let frames = (crossfade.secs * sample_rate as f32) as usize;
+    frames * channels as usize
+}
+
+/// Queues `samples`, keeping `clock` up to date with them when playing in
+/// a synced session.
+fn append(
+    sink: &Sink,
+    channels: u16,
+    sample_rate: u32,
+    samples: Vec<i16>,
+    clock: Option<&Arc<SyncClock>>,
+) {
+    let buffer = SamplesBuffer::new(channels, sample_rate, samples);
+    match clock {
+        Some(clock) => sink.append(Synced::new(buffer, clock.clone())),
+        None => sink.append(buffer),
+    }
+}
+
+/// Adds `data` to the held tail and queues everything that is older than
+/// the fade length. Returns the number of sources appended to the sink.
+pub fn hold_back(
+    tail: &mut HeldTail,
+    data: Vec<i16>,
+    crossfade: Option<Crossfade>,
+    clock: Option<&Arc<SyncClock>>,
+    sink: &Sink,
+) -> usize {
+    let keep = fade_len(crossfade, tail.channels, tail.sample_rate);
+    tail.samples.extend(data);
+    if tail.samples.len() <= keep {
+        return 0;
+    }
+
+    let channels = tail.channels.max(1) as usize;
+    let mut excess = tail.samples.len() - keep;
+    excess -= excess % channels;
+    if excess == 0 {
+        return 0;
+    }
+
+    let out: Vec<i16> = tail.samples.drain(..excess).collect();
+    append(sink, tail.channels, tail.sample_rate, out, clock);
+    1
+}
+
+/// Queues whatever is left in the held tail.
+pub fn flush_tail(tail: &mut HeldTail, clock: Option<&Arc<SyncClock>>, sink: &Sink) -> usize {
+    if tail.samples.is_empty() {
+        return 0;
+    }
+    let out = mem::take(&mut tail.samples);
+    append(sink, tail.channels, tail.sample_rate, out, clock);
+    1
+}
+
+/// Overlaps the held fade-out of the previous track with the head collected
+/// for `next`, queues the mix and carries on with the rest of the head.
+/// Returns the number of sources appended to the sink.
+pub fn fade_in(
+    next: &mut NextTrack,
+    tail: &mut HeldTail,
+    crossfade: Option<Crossfade>,
+    clock: Option<&Arc<SyncClock>>,
+    sink: &Sink,
+) -> usize {
+    let curve = crossfade.map(|c| c.curve).unwrap_or(FadeCurve::Linear);
+    let fade_out = mem::take(&mut next.fade_out);
+    let mut head = mem::take(&mut next.head);
+    let rest = head.split_off(fade_out.len().min(head.len()));
+    next.crossfade = false;
+
+    let mut appended = 0;
+    if !fade_out.is_empty() {
+        let mixed = mix(&fade_out, &head, next.channels, curve);
+        append(sink, next.channels, next.sample_rate, mixed, clock);
+        appended += 1;
+    }
+    appended + hold_back(tail, rest, crossfade, clock, sink)
+}
+
+/// Queues the held fade-out of the previous track as it is, for when the
+/// current track has drained before enough of `next` arrived to overlap it,
+/// and carries on with whatever head there is. Returns the number of
+/// sources of `next` appended to the sink, which leaves out the fade-out.
+pub fn skip_fade(
+    next: &mut NextTrack,
+    tail: &mut HeldTail,
+    crossfade: Option<Crossfade>,
+    sink: &Sink,
+) -> usize {
+    let fade_out = mem::take(&mut next.fade_out);
+    next.crossfade = false;
+    if !fade_out.is_empty() {
+        append(sink, next.channels, next.sample_rate, fade_out, None);
+    }
+    hold_back(tail, mem::take(&mut next.head), crossfade, None, sink)
+}
+
+/// Sums `fade_out` fading out with `head` fading in over the length of
+/// `fade_out`. A short `head` is padded with silence.
+fn mix(fade_out: &[i16], head: &[i16], channels: u16, curve: FadeCurve) -> Vec<i16> {
+    let channels = channels.max(1) as usize;
+    let frames = (fade_out.len() / channels).max(1) as f32;
+
+    fade_out
+        .iter()
+        .enumerate()
+        .map(|(i, &out)| {
+            let t = (i / channels) as f32 / frames;
+            let incoming = head.get(i).copied().unwrap_or(0) as f32;
+            let sample = out as f32 * curve.gain(1.0 - t) + incoming * curve.gain(t);
+            sample.clamp(i16::MIN as f32, i16::MAX as f32) as i16
+        })
+        .collect()
+}
@@ -1,5 +1,6 @@
 use crate::types::ClientState;
 use crate::types::GetReturn;
+use musicman_protocols::SongMeta;
 
 pub fn get_prev_song(state: &ClientState, n: usize) -> GetReturn {
     let mut state = state.lock().unwrap();
@@ -47,3 +48,18 @@ pub fn get_next_song(state: &ClientState, n: usize) -> GetReturn {
     state.current_song = Some(current_playing);
     GetReturn::Ok
 }
+
+pub fn peek_next_song(state: &ClientState) -> Option<SongMeta> {
+    let state = state.lock().unwrap();
+
+    if state.queue.is_empty() {
+        return None;
+    }
+
+    if state.current_song.is_none() {
+        return Some(state.queue[0].clone());
+    }
+
+    let next_index = (state.current_idx + 1) % state.queue.len();
+    Some(state.queue[next_index].clone())
+}
@@ -0,0 +1,162 @@
+use crate::config::{ScrobbleConfig, ScrobbleFormat, config_dir};
+use anyhow::Result;
+use musicman_protocols::SongMeta;
+use serde_json::json;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Appends finished listens to an offline log for another tool to submit.
+/// Next to the log, `<log>.cursor` holds the byte offset up to which entries
+/// have been submitted. We only create it; uploaders move it forward.
+pub struct Scrobbler {
+    format: ScrobbleFormat,
+    path: PathBuf,
+}
+
+impl Scrobbler {
+    pub fn new(config: &ScrobbleConfig) -> Result<Self> {
+        let default_name = match config.format {
+            ScrobbleFormat::ListenBrainz => "listens.jsonl",
+            ScrobbleFormat::Scrobbler => ".scrobbler.log",
+        };
+        let path = match &config.path {
+            Some(path) => path.clone(),
+            None => config_dir()
+                .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?
+                .join(default_name),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        if !path.exists() && config.format == ScrobbleFormat::Scrobbler {
+            fs::write(
+                &path,
+                format!(
+                    "#AUDIOSCROBBLER/1.1\n#TZ/UTC\n#CLIENT/musicman {}\n",
+                    env!("CARGO_PKG_VERSION")
+                ),
+            )?;
+        }
+        let cursor = cursor_path(&path);
+        if !cursor.exists() {
+            // nothing before the current end needs submitting
+            let len = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            fs::write(cursor, format!("{len}\n"))?;
+        }
+
+        Ok(Scrobbler {
+            format: config.format,
+            path,
+        })
+    }
+
+    /// Logs a listen of `song` that started at `started` (unix seconds).
+    pub fn record(&self, song: &SongMeta, started: u64) -> Result<()> {
+        let line = match self.format {
+            ScrobbleFormat::ListenBrainz => listenbrainz_line(song, started),
+            ScrobbleFormat::Scrobbler => scrobbler_line(song, started),
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{line}")?;
+        Ok(())
+    }
+}
+
+fn cursor_path(log: &std::path::Path) -> PathBuf {
+    let mut name = log.file_name().unwrap_or_default().to_os_string();
+    name.push(".cursor");
+    log.with_file_name(name)
+}
+
+fn listenbrainz_line(song: &SongMeta, started: u64) -> String {
+    let mut info = json!({
+        "duration_ms": song.duration as u64 * 1000,
+        "media_player": "musicman",
+        "submission_client": "musicman",
+        "submission_client_version": env!("CARGO_PKG_VERSION"),
+    });
+    if let Some(track) = song.track {
+        info["tracknumber"] = json!(track);
+    }
+    let mut metadata = json!({
+        "artist_name": song.artists.join(", "),
+        "track_name": song.title,
+        "additional_info": info,
+    });
+    if !song.album.is_empty() {
+        metadata["release_name"] = json!(song.album);
+    }
+    json!({ "listened_at": started, "track_metadata": metadata }).to_string()
+}
+
+/// ARTIST, ALBUM, TITLE, TRACKNUM, LENGTH, RATING, TIMESTAMP and MUSICBRAINZ
+/// TRACKID, tab separated. `L` means the track was listened to rather than
+/// skipped.
+fn scrobbler_line(song: &SongMeta, started: u64) -> String {
+    let clean = |s: &str| s.replace(['\t', '\n'], " ");
+    [
+        clean(&song.artists.join(", ")),
+        clean(&song.album),
+        clean(&song.title),
+        song.track.map(|t| t.to_string()).unwrap_or_default(),
+        song.duration.to_string(),
+        "L".to_string(),
+        started.to_string(),
+        String::new(),
+    ]
+    .join("\t")
+}
+
+/// How much of the current track has actually been heard, paused time
+/// excluded.
+pub struct Listen {
+    /// The player's count of tracks started when this one was.
+    pub play: u64,
+    /// Seconds to hear before it's logged, as the server counts plays.
+    needed: u32,
+    started: u64,
+    heard: Duration,
+    logged: bool,
+}
+
+impl Listen {
+    pub fn new(play: u64, needed: u32) -> Self {
+        Listen {
+            play,
+            needed,
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            heard: Duration::ZERO,
+            logged: false,
+        }
+    }
+
+    /// Adds `elapsed` of listening and logs the track the first time it has
+    /// been heard for as long as the server wants before counting a play.
+    /// Tracks under 30 seconds are never logged.
+    pub fn advance(
+        &mut self,
+        elapsed: Duration,
+        song: &SongMeta,
+        scrobbler: &Scrobbler,
+    ) -> Result<()> {
+        self.heard += elapsed;
+        let needed = Duration::from_secs(self.needed as u64);
+        if self.logged || song.duration < 30 || self.heard < needed {
+            return Ok(());
+        }
+
+        self.logged = true;
+        scrobbler.record(song, self.started)
+    }
+}
@@ -0,0 +1,184 @@
+use crate::types::*;
+use rodio::{Source, buffer::SamplesBuffer};
+use std::{
+    sync::{Arc, atomic::Ordering},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Drift left alone, since every correction is audible.
+const TOLERANCE_NS: i64 = 20_000_000;
+
+fn unix_now_ns() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or(0)
+}
+
+impl SyncState {
+    /// A fresh clock for a stream whose first chunk plays at `start_at`,
+    /// in unix milliseconds, if known yet.
+    pub fn new(start_at: Option<u64>) -> Self {
+        Self {
+            clock: Arc::new(SyncClock::default()),
+            anchor: start_at.map(|at| at as i64 * 1_000_000),
+            received: 0,
+        }
+    }
+
+    /// Counts a chunk of `samples` into the received audio.
+    pub fn receive(&mut self, samples: usize, channels: u16, sample_rate: u32) {
+        let frames = (samples / channels.max(1) as usize) as i64;
+        self.received += frames * 1_000_000_000 / sample_rate.max(1) as i64;
+    }
+
+    /// Takes a timing marker: the next chunk received plays at `at`.
+    pub fn mark(&mut self, at: u64) {
+        self.anchor = Some(at as i64 * 1_000_000 - self.received);
+    }
+
+    /// Compares what the sink has played with where the session should be,
+    /// and has the sink skip or wait out the difference once it grows past
+    /// the tolerance.
+    pub fn correct(&self) {
+        let Some(anchor) = self.anchor else {
+            return;
+        };
+        let expected = unix_now_ns() - anchor;
+        if expected < 0 {
+            return;
+        }
+
+        let clock = &self.clock;
+        let ahead = clock.played.load(Ordering::Relaxed) as i64
+            + clock.skew.load(Ordering::Relaxed)
+            - expected;
+        if ahead.abs() > TOLERANCE_NS {
+            clock.skew.fetch_sub(ahead, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Starts the sink at `start_at`, in unix milliseconds, unless another
+/// synced stream has replaced the one `clock` belongs to by then.
+pub fn play_at(start_at: u64, clock: Arc<SyncClock>, sink: RodioSink, player_state: PlayerState) {
+    thread::spawn(move || {
+        let wait = start_at as i64 * 1_000_000 - unix_now_ns();
+        if wait > 0 {
+            thread::sleep(Duration::from_nanos(wait as u64));
+        }
+
+        let Ok(ps) = player_state.lock() else {
+            return;
+        };
+        if ps
+            .sync
+            .as_ref()
+            .is_some_and(|sync| Arc::ptr_eq(&sync.clock, &clock))
+            && let Ok(s) = sink.lock()
+        {
+            s.play();
+        }
+    });
+}
+
+/// A source that reports its progress to a [`SyncClock`] and applies the
+/// skew the clock asks for, a frame at a time.
+pub struct Synced {
+    inner: SamplesBuffer<i16>,
+    clock: Arc<SyncClock>,
+    channels: u16,
+    sample_rate: u32,
+    emitted: u64,
+    reported: u64,
+    silence: u16,
+}
+
+impl Synced {
+    pub fn new(inner: SamplesBuffer<i16>, clock: Arc<SyncClock>) -> Self {
+        Self {
+            channels: inner.channels().max(1),
+            sample_rate: inner.sample_rate().max(1),
+            inner,
+            clock,
+            emitted: 0,
+            reported: 0,
+            silence: 0,
+        }
+    }
+
+    fn frame_ns(&self) -> i64 {
+        1_000_000_000 / self.sample_rate as i64
+    }
+
+    /// Adds the whole frames passed since the last report to the clock.
+    fn report(&mut self) {
+        let frames = self.emitted / self.channels as u64;
+        let ns = frames * 1_000_000_000 / self.sample_rate as u64;
+        self.clock
+            .played
+            .fetch_add(ns - self.reported, Ordering::Relaxed);
+        self.reported = ns;
+    }
+}
+
+impl Iterator for Synced {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.silence > 0 {
+            self.silence -= 1;
+            return Some(0);
+        }
+
+        let channels = self.channels as u64;
+        if self.emitted.is_multiple_of(channels) {
+            let frame_ns = self.frame_ns();
+            loop {
+                let skew = self.clock.skew.load(Ordering::Relaxed);
+                if skew >= frame_ns {
+                    // behind, drop a frame
+                    for _ in 0..channels {
+                        self.inner.next()?;
+                    }
+                    self.emitted += channels;
+                    self.report();
+                    self.clock.skew.fetch_sub(frame_ns, Ordering::Relaxed);
+                } else if skew <= -frame_ns {
+                    // ahead, hold back with a silent frame
+                    self.clock.skew.fetch_add(frame_ns, Ordering::Relaxed);
+                    self.silence = self.channels - 1;
+                    return Some(0);
+                } else {
+                    break;
+                }
+            }
+        }
+
+        let sample = self.inner.next()?;
+        self.emitted += 1;
+        if self.emitted.is_multiple_of(channels) {
+            self.report();
+        }
+        Some(sample)
+    }
+}
+
+impl Source for Synced {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
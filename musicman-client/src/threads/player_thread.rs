@@ -1,9 +1,8 @@
-use crate::types::*;
+use crate::{helpers::send_to_server, mixer, sync, types::*};
 use musicman_protocols::*;
-use rodio::buffer::SamplesBuffer;
-use std::{sync::mpsc::Receiver, thread};
+use std::{mem, sync::mpsc::Receiver, thread};
 
-pub fn player(prx: Receiver<Response>, sink: RodioSink, player_state: PlayerState) {
+pub fn player(stream: Stream, prx: Receiver<Response>, sink: RodioSink, player_state: PlayerState) {
     thread::spawn(move || {
         loop {
             let res = match prx.recv() {
@@ -11,41 +10,192 @@ pub fn player(prx: Receiver<Response>, sink: RodioSink, player_state: PlayerStat
                 Err(_) => continue, // channel closed? just loop
             };
 
-            let mut ps = match player_state.lock() {
+            let mut guard = match player_state.lock() {
                 Ok(l) => l,
                 Err(_) => continue,
             };
+            let ps = &mut *guard;
+
+            let Ok(s) = sink.lock() else {
+                continue;
+            };
+
+            // Synced sessions play tracks back to back so every client's
+            // timeline lines up.
+            let clock = ps.sync.as_ref().map(|sync| sync.clock.clone());
+            let crossfade = match ps.sync {
+                Some(_) => None,
+                None => ps.crossfade,
+            };
 
             match res {
                 Response::SongHeader {
+                    stream_id,
+                    track_id,
+                    prefetch: true,
+                    sample_rate: sr,
+                    channels: ch,
+                    played_after,
+                    ..
+                } => {
+                    // A synced session moves on by itself, without being asked.
+                    if ps.sync.is_some()
+                        && ps
+                            .next
+                            .as_ref()
+                            .is_none_or(|next| next.id != track_id || next.started)
+                    {
+                        ps.next = Some(NextTrack::new(track_id, false));
+                    }
+                    // Only accept the prefetch the watcher asked for; anything else is
+                    // left over from a stream that has since been replaced.
+                    let Some(next) = ps
+                        .next
+                        .as_mut()
+                        .filter(|next| next.id == track_id && !next.started)
+                    else {
+                        // session streams aren't this client's to stop
+                        if ps.sync.is_none() {
+                            send_to_server(&stream, Request::Stop { stream_id });
+                        }
+                        continue;
+                    };
+                    next.stream_id = Some(stream_id);
+                    next.channels = ch;
+                    next.sample_rate = sr;
+                    next.played_after = played_after;
+                    next.started = true;
+
+                    // Tracks in different formats can't be mixed, so the
+                    // previous tail just plays out.
+                    let mut tail = mem::replace(&mut ps.tail, HeldTail::new(ch, sr));
+                    if next.crossfade && tail.channels == ch && tail.sample_rate == sr {
+                        next.fade_out = tail.samples;
+                    } else {
+                        next.crossfade = false;
+                        mixer::flush_tail(&mut tail, clock.as_ref(), &s);
+                    }
+                }
+
+                Response::SongHeader {
+                    stream_id,
                     sample_rate: sr,
                     channels: ch,
                     track_id,
+                    start_at,
+                    played_after,
+                    ..
                 } => {
+                    // A continued stream carries on where the dropped one left
+                    // off, after what is already in the sink.
+                    if ps.continuing.take() == Some(track_id) {
+                        match ps.next.as_mut().filter(|next| next.id == track_id) {
+                            Some(next) => next.stream_id = Some(stream_id),
+                            None => ps.stream_id = Some(stream_id),
+                        }
+                        ps.waiting_for_header = false;
+                        continue;
+                    }
                     ps.channels = ch;
                     ps.sample_rate = sr;
                     ps.current_id = Some(track_id);
+                    ps.stream_id = Some(stream_id);
+                    ps.received = 0;
                     ps.waiting_for_header = false;
+                    ps.stream_done = false;
+                    ps.played_after = played_after;
+                    ps.plays += 1;
+                    ps.next = None;
+                    ps.tail = HeldTail::new(ch, sr);
 
-                    if let Ok(s) = sink.lock() {
-                        s.clear();
-                        s.play();
+                    s.clear();
+                    match start_at {
+                        Some(at) => {
+                            // hold off until the shared start time
+                            let sync = SyncState::new(Some(at));
+                            sync::play_at(
+                                at,
+                                sync.clock.clone(),
+                                sink.clone(),
+                                player_state.clone(),
+                            );
+                            ps.sync = Some(sync);
+                            s.pause();
+                        }
+                        None => {
+                            ps.sync = None;
+                            s.play();
+                        }
                     }
                 }
 
-                Response::SongChunk { data, track_id, .. } => {
-                    if ps.current_id != Some(track_id) {
+                Response::SongChunk {
+                    data,
+                    stream_id,
+                    index,
+                    ..
+                } => {
+                    // The prefetched stream is only requested once the current one has
+                    // ended, so its chunks go straight after the current track in the sink.
+                    if let Some(next) = ps.next.as_mut()
+                        && next.stream_id == Some(stream_id)
+                    {
+                        next.received = index + 1;
+                        if let Some(sync) = ps.sync.as_mut() {
+                            sync.receive(data.len(), next.channels, next.sample_rate);
+                        }
+                        if next.crossfade {
+                            next.head.extend(data);
+                            if next.head.len() >= next.fade_out.len() {
+                                next.sources += mixer::fade_in(
+                                    next,
+                                    &mut ps.tail,
+                                    crossfade,
+                                    clock.as_ref(),
+                                    &s,
+                                );
+                            }
+                        } else {
+                            next.sources +=
+                                mixer::hold_back(&mut ps.tail, data, crossfade, clock.as_ref(), &s);
+                        }
+                        continue;
+                    }
+
+                    if ps.stream_id != Some(stream_id) || ps.stream_done {
                         continue;
                     }
-                    if let Ok(s) = sink.lock() {
-                        let src = SamplesBuffer::new(ps.channels, ps.sample_rate, data);
-                        s.append(src);
+                    ps.received = index + 1;
+                    if let Some(sync) = ps.sync.as_mut() {
+                        sync.receive(data.len(), ps.channels, ps.sample_rate);
+                    }
+                    mixer::hold_back(&mut ps.tail, data, crossfade, clock.as_ref(), &s);
+                }
+
+                Response::Timing { stream_id, at, .. } => {
+                    let next_started = ps
+                        .next
+                        .as_ref()
+                        .is_some_and(|next| next.stream_id == Some(stream_id));
+                    let current = ps.stream_id == Some(stream_id) && !ps.stream_done;
+                    if (next_started || current)
+                        && let Some(sync) = ps.sync.as_mut()
+                    {
+                        sync.mark(at);
                     }
                 }
 
-                Response::EndOfStream { track_id } => {
-                    if ps.current_id == Some(track_id) {
-                        ps.current_id = None;
+                Response::EndOfStream { stream_id, .. } => {
+                    if let Some(next) = ps.next.as_mut()
+                        && next.stream_id == Some(stream_id)
+                    {
+                        if next.crossfade {
+                            next.sources +=
+                                mixer::fade_in(next, &mut ps.tail, crossfade, clock.as_ref(), &s);
+                        }
+                        next.done = true;
+                    } else if ps.stream_id == Some(stream_id) {
+                        ps.stream_done = true;
                     }
                 }
 
@@ -1,18 +1,19 @@
-use crate::{helpers, types::*};
+use crate::{handlers, helpers, types::*};
+use colored::Colorize;
 use musicman_protocols::*;
 use std::{
-    io,
-    net::TcpStream,
+    io, mem,
     sync::mpsc::{Receiver, Sender},
     thread,
+    time::Duration,
 };
 use tabled::settings::{Remove, Style, object::Columns};
 
-type Stream = TcpStream;
-
 pub fn server_interface(
     mut stream: Stream,
     state: ClientState,
+    player_state: PlayerState,
+    sink: RodioSink,
     ptx: Sender<Response>,
     utx: Sender<UiRequest>,
     srx: Receiver<UiResponse>,
@@ -41,6 +42,7 @@ pub fn server_interface(
                     }
                     Response::SongChunk { .. }
                     | Response::SongHeader { .. }
+                    | Response::Timing { .. }
                     | Response::EndOfStream { .. } => {
                         ptx.send(response).unwrap();
                     }
@@ -50,22 +52,167 @@ pub fn server_interface(
                     Response::SearchResults(data) => {
                         helpers::handle_search_response(data, &stream, &state, &utx, &srx)
                     }
+                    Response::Browse(bres) => helpers::handle_browse_response(bres, &utx),
+                    Response::History(hres) => helpers::handle_history_response(hres, &utx),
+                    Response::Radio(songs) => {
+                        let mut st = state.lock().unwrap();
+                        st.radio_pending = false;
+                        if !st.radio {
+                            continue;
+                        }
+                        if songs.is_empty() {
+                            st.radio = false;
+                            utx.send(UiRequest::Display(
+                                "Radio ran out of songs and was turned off.".to_string(),
+                            ))
+                            .unwrap();
+                        } else {
+                            st.queue.extend(songs);
+                        }
+                    }
+                    Response::Session(sres) => {
+                        helpers::handle_session_response(sres, &stream, &state, &player_state, &utx)
+                    }
+                    Response::Clients(clients) => {
+                        let out = match clients.is_empty() {
+                            true => "No clients have registered a name.".to_string(),
+                            false => tabled::Table::new(clients.into_iter().map(|c| ClientTable {
+                                name: c.name,
+                                addr: c.addr,
+                            }))
+                            .with(Style::rounded())
+                            .to_string(),
+                        };
+                        utx.send(UiRequest::Display(out)).unwrap();
+                    }
+                    Response::Control { from, command } => helpers::handle_control_response(
+                        from, command, &stream, &state, &sink, &utx,
+                    ),
+                    // only musicman-admin sends admin requests
+                    Response::Admin(_) => {}
+                    Response::LoggedIn { user, permission } => {
+                        let out = format!("Logged in as {user} ({}).", permission_name(permission));
+                        utx.send(UiRequest::Display(out)).unwrap();
+                    }
+                    Response::Denied(e) => {
+                        let out = match e {
+                            AuthError::Required => {
+                                "The server wants a login, see [login] in client.toml.".to_string()
+                            }
+                            AuthError::InvalidCredentials => "Login failed.".to_string(),
+                            AuthError::Forbidden { needs } => {
+                                format!("That needs the {} permission.", permission_name(needs))
+                            }
+                        };
+                        utx.send(UiRequest::Display(out.red().to_string())).unwrap();
+                    }
                     Response::Error { message } => {
                         utx.send(UiRequest::Display(message)).unwrap();
                     }
+                    // the connection drops next, and is picked up again if
+                    // the server comes back in time
+                    Response::ShuttingDown => {
+                        let out = "The server is shutting down.".yellow().to_string();
+                        utx.send(UiRequest::Display(out)).unwrap();
+                    }
                 },
                 Err(e) => {
-                    if let Some(ioerr) = e.downcast_ref::<io::Error>() {
-                        if ioerr.kind() == io::ErrorKind::UnexpectedEof {
+                    if e.downcast_ref::<io::Error>().is_some() {
+                        if !reconnect(&stream, &state, &player_state, &utx) {
                             utx.send(UiRequest::Shutdown).unwrap();
                             break;
-                        } else {
-                            utx.send(UiRequest::Display("Error: {e}".to_string()))
-                                .unwrap();
                         }
+                    } else {
+                        utx.send(UiRequest::Display(format!("Error: {e}"))).unwrap();
                     }
                 }
             }
         }
     });
 }
+
+/// Attempts at reconnecting before giving up, a second apart at first and
+/// twice as long after each failure.
+const RECONNECT_ATTEMPTS: u32 = 4;
+
+/// Tries to get a dropped connection back and carry on from where it was.
+/// Returns false if the server stays out of reach.
+fn reconnect(
+    stream: &Stream,
+    state: &ClientState,
+    player_state: &PlayerState,
+    utx: &Sender<UiRequest>,
+) -> bool {
+    utx.send(UiRequest::Display(
+        "Lost the connection to the server, reconnecting..."
+            .yellow()
+            .to_string(),
+    ))
+    .unwrap();
+    // keep the watcher from moving on while the sink runs low
+    let waiting = mem::replace(&mut player_state.lock().unwrap().waiting_for_header, true);
+
+    for attempt in 0..RECONNECT_ATTEMPTS {
+        thread::sleep(Duration::from_secs(1 << attempt));
+        if stream.reconnect().is_ok() {
+            pick_up(stream, state, player_state, waiting);
+            utx.send(UiRequest::Display("Reconnected.".to_string()))
+                .unwrap();
+            return true;
+        }
+    }
+    false
+}
+
+/// Tells the new connection what the old one had set up, and continues the
+/// stream that was cut off from the first chunk that didn't arrive.
+fn pick_up(stream: &Stream, state: &ClientState, player_state: &PlayerState, waiting: bool) {
+    let mut ps = player_state.lock().unwrap();
+    let mut st = state.lock().unwrap();
+    handlers::hold_back(stream, ps.crossfade.map(|xf| xf.secs));
+    if let Some(name) = st.session.clone() {
+        st.reattaching = true;
+        helpers::send_to_server(stream, Request::Session(SessionRequest::Attach { name }));
+    }
+    // the server catches synced clients up by itself
+    if ps.sync.is_some() {
+        ps.waiting_for_header = waiting;
+        return;
+    }
+
+    // a play that got lost with the connection
+    if waiting && let Some(song) = &st.current_song {
+        helpers::send_to_server(stream, Request::Play { track_id: song.id });
+        return;
+    }
+    let next = ps.next.as_ref().filter(|next| next.started && !next.done);
+    let (track_id, from_chunk) = if let Some(next) = next {
+        (next.id, next.received)
+    } else if let Some(id) = ps.current_id
+        && !ps.stream_done
+    {
+        ps.next = None;
+        (id, ps.received)
+    } else {
+        // a prefetch that hadn't started yet gets asked for again
+        ps.next = ps.next.take().filter(|next| next.started);
+        ps.waiting_for_header = waiting;
+        return;
+    };
+    ps.continuing = Some(track_id);
+    helpers::send_to_server(
+        stream,
+        Request::Continue {
+            track_id,
+            from_chunk,
+        },
+    );
+}
+
+fn permission_name(permission: Permission) -> &'static str {
+    match permission {
+        Permission::ReadOnly => "read-only",
+        Permission::PlaylistWrite => "playlist-write",
+        Permission::Admin => "admin",
+    }
+}
@@ -2,7 +2,6 @@ use crate::{helpers, types::*};
 use colored::Colorize;
 use reedline::{FileBackedHistory, Reedline, Signal};
 use std::{
-    net::TcpStream,
     path::PathBuf,
     sync::{
         Arc, Mutex,
@@ -13,8 +12,9 @@ use std::{
 };
 
 pub fn user_input(
-    stream: TcpStream,
+    stream: Stream,
     state: Arc<Mutex<ClientStateStruct>>,
+    player_state: PlayerState,
     sink: RodioSink,
     urx: Receiver<UiRequest>,
     stx: Sender<UiResponse>,
@@ -66,11 +66,12 @@ pub fn user_input(
                     }
                 }
             } else {
-                if let Err(_) = helpers::musicman_prompt(&stream, &state, &sink, &mut editor) {
+                if helpers::musicman_prompt(&stream, &state, &player_state, &sink, &mut editor)
+                    .is_err()
+                {
                     break;
                 }
             }
         }
     })
 }
-
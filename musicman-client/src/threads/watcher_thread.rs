@@ -1,40 +1,106 @@
-use crate::{helpers::send_to_server, player, types::*};
+use crate::{
+    helpers::send_to_server,
+    mixer, player,
+    scrobble::{Listen, Scrobbler},
+    types::*,
+};
+use colored::Colorize;
 use musicman_protocols::*;
-use std::{net::TcpStream, thread, thread::sleep, time::Duration};
+use std::{
+    sync::mpsc::Sender,
+    thread,
+    thread::sleep,
+    time::{Duration, Instant},
+};
 
 pub fn watcher_thread(
-    stream: TcpStream,
+    stream: Stream,
     sink: RodioSink,
     state: ClientState,
     player_state: PlayerState,
+    scrobbler: Option<Scrobbler>,
+    utx: Sender<UiRequest>,
 ) {
     thread::spawn(move || {
+        let mut listen: Option<Listen> = None;
+        let mut last_tick = Instant::now();
+        let mut last_sync: Option<(String, SessionUpdate, Instant)> = None;
+
         loop {
+            sleep(Duration::from_millis(500));
+            let elapsed = last_tick.elapsed();
+            last_tick = Instant::now();
+            extend_radio(&state, &stream);
+
             let mut ps = match player_state.lock() {
                 Ok(p) => p,
-                Err(_) => {
-                    sleep(Duration::from_millis(500));
-                    continue;
-                }
+                Err(_) => continue,
             };
 
+            sync_session(&ps, &state, &stream, &mut last_sync);
+
             if ps.waiting_for_header {
-                drop(ps);
-                sleep(Duration::from_millis(500));
                 continue;
             }
 
-            let empty = match sink.lock() {
-                Ok(s) => s.empty(),
-                Err(_) => {
-                    sleep(Duration::from_millis(500));
+            let (queued, paused) = match sink.lock() {
+                Ok(s) => (s.len(), s.is_paused()),
+                Err(_) => continue,
+            };
+
+            if let Some(id) = ps.current_id
+                && queued > 0
+                && !paused
+            {
+                if ps.position_id != Some(id) {
+                    ps.position_id = Some(id);
+                    ps.position = Duration::ZERO;
+                }
+                ps.position += elapsed;
+
+                if let Some(scrobbler) = &scrobbler {
+                    track_listen(&mut listen, &ps, elapsed, &state, scrobbler, &utx);
+                }
+            }
+
+            if let Some(sync) = &ps.sync {
+                if queued > 0 && !paused {
+                    sync.correct();
+                }
+                follow_session(&mut ps, &state, queued);
+                continue;
+            }
+
+            if let Some(next) = &ps.next {
+                if !next.started && queued == 0 {
+                    // The prefetch didn't arrive in time, fall back to a regular play.
+                    ps.next = None;
+                } else {
+                    // Until its head has been mixed in, the fade-out isn't in the
+                    // sink yet, so the current track hasn't really drained.
+                    if next.started && next.crossfade && queued == 0 {
+                        skip_fade(&mut ps, &sink);
+                    } else if next.started && queued <= next.sources {
+                        // Everything left in the sink belongs to the prefetched track
+                        // once the current one has drained.
+                        promote_next(&mut ps, &state, &sink, &stream);
+                    }
                     continue;
                 }
-            };
+            }
+
+            if queued > 0 {
+                if ps.stream_done && ps.current_id.is_some() {
+                    prefetch_next(&mut ps, &state, &sink, &stream);
+                }
+                continue;
+            }
 
-            if !empty {
-                drop(ps);
-                sleep(Duration::from_millis(500));
+            // A track shorter than the crossfade may sit entirely in the held tail.
+            if ps.stream_done && !ps.tail.samples.is_empty() {
+                if let Ok(s) = sink.lock() {
+                    mixer::flush_tail(&mut ps.tail, None, &s);
+                }
                 continue;
             }
 
@@ -46,11 +112,217 @@ pub fn watcher_thread(
             }
 
             if let Ok(st) = state.lock() {
-                let track_id = st.current_song.clone().unwrap().id.clone();
+                let track_id = st.current_song.clone().unwrap().id;
                 send_to_server(&stream, Request::Play { track_id });
             }
-
-            sleep(Duration::from_millis(500));
         }
     });
 }
+
+/// Songs asked for each time the radio tops up the queue.
+const RADIO_BATCH: usize = 10;
+
+/// Asks the server for related songs once the queue is within a couple of
+/// songs of its end, so they arrive before it would wrap around.
+fn extend_radio(state: &ClientState, stream: &Stream) {
+    let mut st = state.lock().unwrap();
+    if !st.radio || st.radio_pending || st.queue.is_empty() {
+        return;
+    }
+    let remaining = st.queue.len().saturating_sub(st.current_idx + 1);
+    if remaining > 2 {
+        return;
+    }
+
+    st.radio_pending = true;
+    let seeds = st.queue.iter().rev().take(5).map(|s| s.id).collect();
+    let exclude = st.queue.iter().map(|s| s.id).collect();
+    send_to_server(
+        stream,
+        Request::Radio {
+            seeds,
+            exclude,
+            count: RADIO_BATCH,
+        },
+    );
+}
+
+/// How often the position is sent while nothing else changes.
+const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Sends the queue and modes to the attached session whenever they change,
+/// and the position every few seconds while it moves.
+fn sync_session(
+    ps: &PlayerStateStruct,
+    state: &ClientState,
+    stream: &Stream,
+    last: &mut Option<(String, SessionUpdate, Instant)>,
+) {
+    let st = state.lock().unwrap();
+    let Some(name) = st.session.clone() else {
+        *last = None;
+        return;
+    };
+    let current = st.current_song.as_ref().map(|s| s.id);
+    let update = SessionUpdate {
+        queue: st.queue.iter().map(|s| s.id).collect(),
+        current_idx: st.current_idx,
+        position: match ps.position_id == current {
+            true => ps.position.as_secs() as u32,
+            false => 0,
+        },
+        modes: SessionModes {
+            radio: st.radio,
+            crossfade: ps
+                .crossfade
+                .map(|xf| ((xf.secs * 1000.0) as u32, xf.curve.name().to_string())),
+        },
+    };
+    drop(st);
+
+    let changed = match last {
+        Some((prev_name, prev, at)) if *prev_name == name => {
+            prev.queue != update.queue
+                || prev.current_idx != update.current_idx
+                || prev.modes != update.modes
+                || (prev.position != update.position && at.elapsed() >= SYNC_INTERVAL)
+        }
+        _ => true,
+    };
+    if changed {
+        send_to_server(
+            stream,
+            Request::Session(SessionRequest::Update(update.clone())),
+        );
+        *last = Some((name, update, Instant::now()));
+    }
+}
+
+fn track_listen(
+    listen: &mut Option<Listen>,
+    ps: &PlayerStateStruct,
+    elapsed: Duration,
+    state: &ClientState,
+    scrobbler: &Scrobbler,
+    utx: &Sender<UiRequest>,
+) {
+    let Some(song) = state.lock().unwrap().current_song.clone() else {
+        return;
+    };
+    if ps.current_id != Some(song.id) {
+        return;
+    }
+    // a replay or another round of a loop is a new listen
+    if listen.as_ref().is_none_or(|l| l.play != ps.plays) {
+        *listen = Some(Listen::new(ps.plays, ps.played_after));
+    }
+    if let Some(listen) = listen
+        && let Err(e) = listen.advance(elapsed, &song, scrobbler)
+    {
+        let out = format!("Could not write scrobble log: {e}");
+        utx.send(UiRequest::Display(out.red().to_string())).ok();
+    }
+}
+
+/// Requests the track the queue expects next, and decides whether it will be
+/// crossfaded into. Consecutive tracks of the same album always play gapless.
+fn prefetch_next(
+    ps: &mut PlayerStateStruct,
+    state: &ClientState,
+    sink: &RodioSink,
+    stream: &Stream,
+) {
+    let current = state.lock().unwrap().current_song.clone();
+    let next = player::peek_next_song(state);
+
+    let crossfade = match (&current, &next) {
+        (Some(current), Some(next)) => {
+            ps.crossfade.is_some() && (current.album.is_empty() || current.album != next.album)
+        }
+        _ => false,
+    };
+    if !crossfade && let Ok(s) = sink.lock() {
+        mixer::flush_tail(&mut ps.tail, None, &s);
+    }
+
+    if let Some(song) = next {
+        ps.next = Some(NextTrack::new(song.id, crossfade));
+        send_to_server(stream, Request::Prefetch { track_id: song.id });
+    }
+}
+
+/// A synced session moves on to the next track by itself, so this only
+/// keeps the player and the queue in step with what is playing.
+fn follow_session(ps: &mut PlayerStateStruct, state: &ClientState, queued: usize) {
+    if let Some(next) = &ps.next
+        && next.started
+        && queued <= next.sources
+        && let Some(next) = ps.next.take()
+    {
+        ps.current_id = Some(next.id);
+        ps.stream_id = next.stream_id;
+        ps.channels = next.channels;
+        ps.sample_rate = next.sample_rate;
+        ps.stream_done = next.done;
+    }
+
+    let Some(id) = ps.current_id else {
+        return;
+    };
+    let mut st = state.lock().unwrap();
+    if st.current_song.as_ref().is_some_and(|s| s.id == id) {
+        return;
+    }
+    if let Some(idx) = st.queue.iter().position(|s| s.id == id) {
+        st.current_idx = idx;
+        st.current_song = Some(st.queue[idx].clone());
+    }
+}
+
+/// Plays the current track's fade-out without the overlap, the next track's
+/// head having arrived too late for it.
+fn skip_fade(ps: &mut PlayerStateStruct, sink: &RodioSink) {
+    let crossfade = ps.crossfade;
+    let Some(next) = ps.next.as_mut() else {
+        return;
+    };
+    if let Ok(s) = sink.lock() {
+        next.sources += mixer::skip_fade(next, &mut ps.tail, crossfade, &s);
+    }
+}
+
+/// Makes the prefetched track the current one, provided the queue still
+/// expects it to come next. Otherwise the prefetched audio is dropped so the
+/// regular play path picks up whatever the queue holds now.
+fn promote_next(
+    ps: &mut PlayerStateStruct,
+    state: &ClientState,
+    sink: &RodioSink,
+    stream: &Stream,
+) {
+    let Some(next) = ps.next.take() else {
+        return;
+    };
+
+    if player::peek_next_song(state).map(|s| s.id) != Some(next.id) {
+        if let Some(stream_id) = next.stream_id
+            && !next.done
+        {
+            send_to_server(stream, Request::Stop { stream_id });
+        }
+        ps.tail.samples.clear();
+        if let Ok(s) = sink.lock() {
+            s.clear();
+        }
+        return;
+    }
+    player::get_next_song(state, 1);
+
+    ps.current_id = Some(next.id);
+    ps.stream_id = next.stream_id;
+    ps.channels = next.channels;
+    ps.sample_rate = next.sample_rate;
+    ps.stream_done = next.done;
+    ps.played_after = next.played_after;
+    ps.plays += 1;
+}
@@ -0,0 +1,245 @@
+use crate::config::{self, TlsConfig};
+use anyhow::{Result, anyhow};
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme, StreamOwned,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{WebPkiSupportedAlgorithms, ring, verify_tls12_signature, verify_tls13_signature},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+const BUF_SIZE: usize = 16 * 1024;
+
+/// Accepts the server's certificate if it has the fingerprint we expect, or
+/// any certificate when we don't know it yet. Handshake signatures are still
+/// checked, so the server has to hold the certificate's key.
+#[derive(Debug)]
+struct Pinned {
+    expected: Option<String>,
+    seen: Mutex<Option<String>>,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for Pinned {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let seen = fingerprint(end_entity);
+        *self.seen.lock().unwrap() = Some(seen.clone());
+        match &self.expected {
+            Some(expected) if !same_fingerprint(expected, &seen) => Err(rustls::Error::General(
+                "certificate fingerprint mismatch".to_string(),
+            )),
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// A TLS connection to the server. Reads decrypt on the reading thread and
+/// writes encrypt on the writing one; they share the session under a lock
+/// that's never held while waiting on the socket.
+pub struct TlsStream {
+    /// A handle on the same socket to wait for data on without the lock.
+    socket: TcpStream,
+    tls: Mutex<StreamOwned<ClientConnection, TcpStream>>,
+    /// Decrypted but not read yet.
+    plain: Mutex<Vec<u8>>,
+}
+
+/// Connects to `addr` over TLS.
+pub fn connect(addr: &str, config: &TlsConfig) -> Result<TlsStream> {
+    let known = known_fingerprint(addr);
+    let pinned = config.fingerprint.is_some();
+    let verifier = Arc::new(Pinned {
+        expected: config.fingerprint.clone().or_else(|| known.clone()),
+        seen: Mutex::new(None),
+        algorithms: ring::default_provider().signature_verification_algorithms,
+    });
+    let client = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+    let name = ServerName::try_from(host.trim_matches(['[', ']']).to_string())?;
+    let mut conn = ClientConnection::new(Arc::new(client), name)?;
+    let mut socket = TcpStream::connect(addr)?;
+
+    while conn.is_handshaking() {
+        if let Err(e) = conn.complete_io(&mut socket) {
+            let seen = verifier.seen.lock().unwrap().clone();
+            return Err(match (&verifier.expected, seen) {
+                (Some(expected), Some(seen)) if !same_fingerprint(expected, &seen) => {
+                    let fix = match pinned {
+                        true => "update the fingerprint in client.toml",
+                        false => "remove it from known_servers",
+                    };
+                    anyhow!(
+                        "The server's certificate changed!\n  expected {expected}\n  got      {seen}\nIf that's expected, {fix}."
+                    )
+                }
+                _ => e.into(),
+            });
+        }
+    }
+
+    if !pinned
+        && known.is_none()
+        && let Some(seen) = verifier.seen.lock().unwrap().as_ref()
+    {
+        remember(addr, seen)?;
+        println!("Trusting the certificate of {addr} from now on:\n  {seen}");
+    }
+
+    Ok(TlsStream {
+        socket: socket.try_clone()?,
+        tls: Mutex::new(StreamOwned::new(conn, socket)),
+        plain: Mutex::new(Vec::new()),
+    })
+}
+
+impl Read for &TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut raw = vec![0u8; BUF_SIZE];
+        loop {
+            {
+                let mut plain = self.plain.lock().unwrap();
+                if !plain.is_empty() {
+                    let n = buf.len().min(plain.len());
+                    buf[..n].copy_from_slice(&plain[..n]);
+                    plain.drain(..n);
+                    return Ok(n);
+                }
+            }
+
+            // nothing decrypted yet, so wait for more without the lock
+            let n = (&self.socket).read(&mut raw)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let mut tls = self.tls.lock().unwrap();
+            let StreamOwned { conn, sock } = &mut *tls;
+            let mut plain = self.plain.lock().unwrap();
+            let mut data = &raw[..n];
+            let mut closed = false;
+            // Drained as it's decrypted: a record split across reads can
+            // leave more than rustls buffers itself.
+            while !data.is_empty() {
+                conn.read_tls(&mut data)?;
+                let state = conn.process_new_packets().map_err(io::Error::other)?;
+                let start = plain.len();
+                plain.resize(start + state.plaintext_bytes_to_read(), 0);
+                conn.reader().read_exact(&mut plain[start..])?;
+                closed |= state.peer_has_closed();
+            }
+            // alerts and key updates
+            while conn.wants_write() {
+                conn.write_tls(sock)?;
+            }
+            if closed && plain.is_empty() {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+impl Write for &TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tls.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.tls.lock().unwrap().flush()
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self).flush()
+    }
+}
+
+/// SHA-256 of the certificate, as `openssl x509 -fingerprint -sha256` shows
+/// it.
+fn fingerprint(cert: &CertificateDer<'_>) -> String {
+    Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn same_fingerprint(a: &str, b: &str) -> bool {
+    let normalize = |s: &str| s.replace(':', "").to_ascii_uppercase();
+    normalize(a) == normalize(b)
+}
+
+fn known_servers_file() -> Option<PathBuf> {
+    config::config_dir().map(|d| d.join("known_servers"))
+}
+
+/// The fingerprint trusted for `addr` on first use, if any.
+fn known_fingerprint(addr: &str) -> Option<String> {
+    let data = std::fs::read_to_string(known_servers_file()?).ok()?;
+    data.lines()
+        .filter_map(|line| line.split_once(' '))
+        .find(|(server, _)| *server == addr)
+        .map(|(_, fingerprint)| fingerprint.trim().to_string())
+}
+
+fn remember(addr: &str, fingerprint: &str) -> Result<()> {
+    let path = known_servers_file().ok_or_else(|| anyhow!("No config directory"))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{addr} {fingerprint}")?;
+    Ok(())
+}
@@ -5,30 +5,101 @@ pub struct MusicmanHighlighter {
     pub commands: Vec<&'static str>,
     pub subcommands: Vec<(&'static str, Vec<&'static str>)>,
     pub takes_parameters: Vec<&'static str>,
+    /// Commands that take free text when no subcommand matches.
+    pub takes_text: Vec<&'static str>,
 }
 
 impl MusicmanHighlighter {
     pub fn new() -> Self {
         Self {
             commands: vec![
-                "clear", "exit", "ls", "next", "p", "pause", "pl", "playlist", "prev", "replay",
-                "search", "show",
+                "album",
+                "albums",
+                "artists",
+                "clear",
+                "crossfade",
+                "exit",
+                "genres",
+                "history",
+                "ls",
+                "next",
+                "p",
+                "pause",
+                "pl",
+                "playlist",
+                "prev",
+                "radio",
+                "remote",
+                "replay",
+                "search",
+                "session",
+                "show",
+                "stats",
+                "vol",
+                "volume",
+                "xf",
+                "years",
             ],
             subcommands: vec![
-                ("pl", vec!["new", "load", "show", "ls"]),
-                ("playlist", vec!["new", "load", "show", "ls"]),
+                (
+                    "pl",
+                    vec![
+                        "new", "smart", "load", "show", "ls", "add", "remove", "move", "mv",
+                        "rename", "delete", "rm", "share", "import", "export",
+                    ],
+                ),
+                (
+                    "playlist",
+                    vec![
+                        "new", "smart", "load", "show", "ls", "add", "remove", "move", "mv",
+                        "rename", "delete", "rm", "share", "import", "export",
+                    ],
+                ),
                 ("search", vec!["artist", "a", "title", "t"]),
+                ("stats", vec!["tracks", "t", "artists", "a", "song", "s"]),
+                ("radio", vec!["on", "off"]),
+                ("session", vec!["ls", "show", "off", "sync"]),
+                ("remote", vec!["ls"]),
+                ("crossfade", vec!["off"]),
+                ("xf", vec!["off"]),
             ],
             takes_parameters: vec![
+                "pl add",
+                "pl delete",
+                "pl export",
+                "pl import",
                 "pl load",
+                "pl move",
+                "pl mv",
                 "pl new",
+                "pl remove",
+                "pl rename",
+                "pl rm",
+                "pl share",
+                "pl smart",
+                "playlist add",
+                "playlist delete",
+                "playlist export",
+                "playlist import",
                 "playlist load",
+                "playlist move",
+                "playlist mv",
                 "playlist new",
+                "playlist remove",
+                "playlist rename",
+                "playlist rm",
+                "playlist share",
+                "playlist smart",
                 "search a",
                 "search t",
                 "search artist",
                 "search title",
+                "stats a",
+                "stats artists",
+                "stats t",
+                "stats tracks",
             ],
+            takes_text: vec!["album", "albums", "history", "remote", "search", "session"],
         }
     }
 }
@@ -36,8 +107,15 @@ impl MusicmanHighlighter {
 impl Highlighter for MusicmanHighlighter {
     fn highlight(&self, line: &str, _cursor: usize) -> StyledText {
         let mut out = StyledText::new();
+        let mut words = line.split_whitespace();
+        let first = words.next().unwrap_or("");
+        let free_text = self.takes_text.contains(&first)
+            && !self
+                .subcommands
+                .iter()
+                .any(|(c, subs)| *c == first && subs.contains(&words.next().unwrap_or("")));
 
-        for (i, chunk) in line.split_inclusive(|c| c == ' ').enumerate() {
+        for (i, chunk) in line.split_inclusive(' ').enumerate() {
             let trimmed = chunk.trim_end();
 
             let style = if i == 0 {
@@ -58,6 +136,8 @@ impl Highlighter for MusicmanHighlighter {
 
                 if sub_known {
                     Style::new().fg(Color::LightPurple).bold()
+                } else if free_text {
+                    Style::new().fg(Color::White).bold()
                 } else {
                     Style::new().fg(Color::Red)
                 }
@@ -68,7 +148,7 @@ impl Highlighter for MusicmanHighlighter {
                     .collect::<Vec<&str>>()
                     .join(" ");
 
-                let is_param = self.takes_parameters.contains(&cmd.trim());
+                let is_param = free_text || self.takes_parameters.contains(&cmd.trim());
                 if is_param {
                     Style::new().fg(Color::White).bold()
                 } else {
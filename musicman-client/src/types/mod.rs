@@ -1,7 +1,12 @@
-use musicman_protocols::SongMeta;
+use musicman_protocols::{SongMeta, StreamId};
 use std::{
     fmt,
-    sync::{Arc, Mutex},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicI64, AtomicU64},
+    },
+    time::Duration,
 };
 use tabled::Tabled;
 use uuid::Uuid;
@@ -11,17 +16,135 @@ pub use linereader::*;
 mod highlighter;
 pub use highlighter::*;
 
+mod stream;
+pub use stream::*;
+
 pub struct ClientStateStruct {
     pub current_song: Option<SongMeta>,
     pub queue: Vec<SongMeta>,
     pub current_idx: usize,
+    pub pending_export: Option<PathBuf>,
+    pub radio: bool,
+    pub radio_pending: bool,
+    pub session: Option<String>,
+    /// Set while attaching to `session` again after a reconnect, which
+    /// carries on with the local playback rather than the session's.
+    pub reattaching: bool,
 }
 
 pub struct PlayerStateStruct {
     pub channels: u16,
     pub sample_rate: u32,
     pub current_id: Option<Uuid>,
+    /// The stream `current_id` is coming in on.
+    pub stream_id: Option<StreamId>,
+    /// Index of the next chunk expected on `stream_id`.
+    pub received: u32,
+    /// The track a stream cut off by a reconnect is being continued for.
+    pub continuing: Option<Uuid>,
     pub waiting_for_header: bool,
+    pub stream_done: bool,
+    /// Seconds of `current_id` after which it counts as played.
+    pub played_after: u32,
+    /// Counts the tracks started, so playing the same one again is told
+    /// apart from carrying on with it.
+    pub plays: u64,
+    pub next: Option<NextTrack>,
+    pub tail: HeldTail,
+    pub crossfade: Option<Crossfade>,
+    /// How far into `position_id` playback has got, paused time excluded.
+    pub position: Duration,
+    pub position_id: Option<Uuid>,
+    /// Set while playing in step with a synced session.
+    pub sync: Option<SyncState>,
+}
+
+/// Playback clock of a synced session, shared with the sources in the sink.
+#[derive(Default)]
+pub struct SyncClock {
+    /// Nanoseconds of audio the sink has played through.
+    pub played: AtomicU64,
+    /// Nanoseconds to jump, forwards by dropping frames when positive and
+    /// back by playing silence when negative.
+    pub skew: AtomicI64,
+}
+
+/// Where playback of a synced session should be.
+pub struct SyncState {
+    pub clock: Arc<SyncClock>,
+    /// Unix nanoseconds at which the first received audio should play,
+    /// once the server has said.
+    pub anchor: Option<i64>,
+    /// Nanoseconds of audio received since the clock started.
+    pub received: i64,
+}
+
+/// The track being prefetched while the current one is still playing.
+pub struct NextTrack {
+    pub id: Uuid,
+    /// Set once the prefetch stream's header arrives.
+    pub stream_id: Option<StreamId>,
+    /// Index of the next chunk expected on `stream_id`.
+    pub received: u32,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub played_after: u32,
+    pub started: bool,
+    pub done: bool,
+    pub sources: usize,
+    pub crossfade: bool,
+    pub fade_out: Vec<i16>,
+    pub head: Vec<i16>,
+}
+
+impl NextTrack {
+    pub fn new(id: Uuid, crossfade: bool) -> Self {
+        Self {
+            id,
+            stream_id: None,
+            received: 0,
+            channels: 2,
+            sample_rate: 48000,
+            played_after: 0,
+            started: false,
+            done: false,
+            sources: 0,
+            crossfade,
+            fade_out: Vec::new(),
+            head: Vec::new(),
+        }
+    }
+}
+
+/// The last samples received for a track, kept out of the sink so they can
+/// be overlapped with the start of the next one.
+pub struct HeldTail {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: Vec<i16>,
+}
+
+impl HeldTail {
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        Self {
+            channels,
+            sample_rate,
+            samples: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FadeCurve {
+    Linear,
+    EqualPower,
+    SCurve,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Crossfade {
+    pub secs: f32,
+    pub curve: FadeCurve,
 }
 
 #[derive(PartialEq)]
@@ -45,7 +168,7 @@ pub struct UiResponse(pub String);
 #[derive(Tabled)]
 pub struct SongTable {
     #[tabled(rename = "Sl.no")]
-    pub id: u16,
+    pub id: usize,
 
     #[tabled(rename = "*")]
     pub playing: PlayingDisplay,
@@ -70,6 +193,75 @@ pub struct PlaylistTable {
 
     #[tabled(rename = "Entries")]
     pub length: usize,
+
+    #[tabled(rename = "Rule")]
+    pub rule: String,
+
+    #[tabled(rename = "Shared")]
+    pub shared: String,
+}
+
+#[derive(Tabled)]
+pub struct SessionTable {
+    #[tabled(rename = "Name")]
+    pub name: String,
+
+    #[tabled(rename = "Queue")]
+    pub len: usize,
+
+    #[tabled(rename = "Clients")]
+    pub clients: usize,
+
+    #[tabled(rename = "Sync")]
+    pub sync: String,
+}
+
+#[derive(Tabled)]
+pub struct ClientTable {
+    #[tabled(rename = "Name")]
+    pub name: String,
+
+    #[tabled(rename = "Address")]
+    pub addr: String,
+}
+
+#[derive(Tabled)]
+pub struct GroupTable {
+    #[tabled(rename = "Sl.no")]
+    pub id: u16,
+
+    #[tabled(rename = "Name")]
+    pub name: String,
+
+    #[tabled(rename = "Tracks")]
+    pub tracks: usize,
+}
+
+#[derive(Tabled)]
+pub struct HistoryTable {
+    #[tabled(rename = "When")]
+    pub when: String,
+
+    #[tabled(rename = "Title")]
+    pub title: String,
+
+    #[tabled(rename = "Artists")]
+    pub artists: String,
+
+    #[tabled(rename = "Client")]
+    pub client: String,
+}
+
+#[derive(Tabled)]
+pub struct PlaysTable {
+    #[tabled(rename = "Sl.no")]
+    pub id: u16,
+
+    #[tabled(rename = "Name")]
+    pub name: String,
+
+    #[tabled(rename = "Plays")]
+    pub plays: u32,
 }
 
 pub struct PlayingDisplay(pub bool);
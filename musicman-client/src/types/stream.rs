@@ -0,0 +1,97 @@
+use crate::{config::TlsConfig, helpers, tls};
+use musicman_protocols::Request;
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    os::unix::net::UnixStream,
+    sync::{Arc, Mutex, RwLock},
+};
+
+/// The connection to the server. Clones share it, so a reconnect carries
+/// over to every thread holding one.
+#[derive(Clone)]
+pub struct Stream {
+    conn: Arc<RwLock<Conn>>,
+    addr: String,
+    tls: TlsConfig,
+    /// Requests every new connection starts with.
+    greeting: Arc<Mutex<Vec<Request>>>,
+    /// Held while a frame is written, so frames sent from different threads
+    /// don't interleave.
+    sending: Arc<Mutex<()>>,
+}
+
+/// TCP, possibly through TLS, or a Unix socket for `unix:/path` addresses.
+enum Conn {
+    Tcp(TcpStream),
+    Tls(Box<tls::TlsStream>),
+    Unix(UnixStream),
+}
+
+impl Conn {
+    fn connect(addr: &str, tls: &TlsConfig) -> anyhow::Result<Conn> {
+        if let Some(path) = addr.strip_prefix("unix:") {
+            // local, so TLS would gain nothing
+            return Ok(Conn::Unix(UnixStream::connect(path)?));
+        }
+        Ok(match tls.enabled {
+            true => Conn::Tls(Box::new(tls::connect(addr, tls)?)),
+            false => Conn::Tcp(TcpStream::connect(addr)?),
+        })
+    }
+}
+
+impl Stream {
+    pub fn connect(addr: &str, tls: &TlsConfig) -> anyhow::Result<Stream> {
+        Ok(Stream {
+            conn: Arc::new(RwLock::new(Conn::connect(addr, tls)?)),
+            addr: addr.to_string(),
+            tls: tls.clone(),
+            greeting: Arc::new(Mutex::new(Vec::new())),
+            sending: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Sends `request`, and sends it again first thing after a reconnect.
+    pub fn greet(&self, request: Request) {
+        helpers::send_to_server(self, request.clone());
+        self.greeting.lock().unwrap().push(request);
+    }
+
+    /// Replaces a dropped connection with a new one to the same address
+    /// and repeats the greeting on it.
+    pub fn reconnect(&self) -> anyhow::Result<()> {
+        let conn = Conn::connect(&self.addr, &self.tls)?;
+        *self.conn.write().unwrap() = conn;
+        for request in self.greeting.lock().unwrap().iter() {
+            helpers::send_to_server(self, request.clone());
+        }
+        Ok(())
+    }
+
+    /// Writes `frame` whole before any other thread gets to write.
+    pub fn send(&self, frame: &[u8]) -> io::Result<()> {
+        let _sending = self.sending.lock().unwrap();
+        match &*self.conn.read().unwrap() {
+            Conn::Tcp(s) => (&*s).write_all(frame),
+            Conn::Tls(s) => (&**s).write_all(frame),
+            Conn::Unix(s) => (&*s).write_all(frame),
+        }
+    }
+}
+
+impl Read for &Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &*self.conn.read().unwrap() {
+            Conn::Tcp(s) => (&*s).read(buf),
+            Conn::Tls(s) => (&**s).read(buf),
+            Conn::Unix(s) => (&*s).read(buf),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub enum AdminRequest {
+    /// Re-reads the music directory. Unless `full`, files unchanged since
+    /// the last scan keep their entries.
+    Rescan {
+        full: bool,
+    },
+    Stats,
+    Clients,
+    Disconnect {
+        addr: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub struct ServerStats {
+    pub songs: usize,
+    // seconds
+    pub uptime: u64,
+    pub clients: usize,
+    pub active_streams: usize,
+    pub bytes_sent: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub struct ConnectedClient {
+    pub addr: String,
+    pub name: Option<String>,
+    pub session: Option<String>,
+    // the last track streamed to it, by title
+    pub track: Option<(Uuid, String)>,
+    // unix seconds
+    pub connected: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub enum AdminResponse {
+    /// Sent while a rescan runs, `done` of `total` files read.
+    Progress {
+        done: usize,
+        total: usize,
+    },
+    Rescanned {
+        songs: usize,
+        added: usize,
+        removed: usize,
+    },
+    Stats(ServerStats),
+    Clients(Vec<ConnectedClient>),
+    Disconnected {
+        addr: String,
+    },
+}
@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+/// What a user may do. Each level allows everything the ones before it do.
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Permission {
+    /// Listen, browse and control playback.
+    #[default]
+    ReadOnly,
+    /// Also create, edit and delete playlists.
+    PlaylistWrite,
+    /// Also send admin requests without the admin token.
+    Admin,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub enum Credentials {
+    Token(String),
+    Password { user: String, password: String },
+}
+
+/// Why a request was refused.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub enum AuthError {
+    /// The server wants a login before anything else.
+    Required,
+    /// Unknown user or token, or the wrong password.
+    InvalidCredentials,
+    /// Logged in, but the request needs `needs`.
+    Forbidden { needs: Permission },
+}
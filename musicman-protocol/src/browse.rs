@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub enum BrowseRequest {
+    Artists,
+    Albums { artist: String },
+    // answered with SearchResults, in disc and track order
+    Album { name: String },
+    Genres,
+    Years,
+}
+
+/// An artist, album, genre or year and how many tracks it has.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub struct BrowseGroup {
+    pub name: String,
+    pub tracks: usize,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub enum BrowseResponse {
+    Artists(Vec<BrowseGroup>),
+    Albums {
+        artist: String,
+        albums: Vec<BrowseGroup>,
+    },
+    Genres(Vec<BrowseGroup>),
+    Years(Vec<BrowseGroup>),
+}
@@ -0,0 +1,57 @@
+use crate::SongMeta;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub enum HistoryRequest {
+    Recent { limit: usize },
+    // `within` is in seconds, `None` for all time
+    TopTracks { within: Option<u64>, limit: usize },
+    TopArtists { within: Option<u64>, limit: usize },
+    PlayCount { track_id: Uuid },
+}
+
+/// A logged play. `time` is in unix seconds and `client` names whoever
+/// was listening.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub struct PlayEvent {
+    pub time: u64,
+    pub track_id: Uuid,
+    pub client: String,
+    /// The user whose history it goes into. Not stored, since each user's
+    /// plays are kept in a file of their own.
+    #[serde(skip)]
+    pub user: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub struct PlayedSong {
+    pub song: SongMeta,
+    pub time: u64,
+    pub client: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub struct SongPlays {
+    pub song: SongMeta,
+    pub plays: u32,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub struct ArtistPlays {
+    pub name: String,
+    pub plays: u32,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub enum HistoryResponse {
+    // newest first
+    Recent(Vec<PlayedSong>),
+    TopTracks(Vec<SongPlays>),
+    TopArtists(Vec<ArtistPlays>),
+    PlayCount {
+        song: SongMeta,
+        plays: u32,
+        last_played: Option<u64>,
+    },
+}
@@ -2,34 +2,134 @@ use crate::*;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+/// Identifies one stream of a track, so its header, chunks and end can be
+/// told apart from those of other streams on the same connection.
+pub type StreamId = u64;
+
+/// Frames in each chunk of a stream, except for a shorter last one. Chunk
+/// `index` starts `index * CHUNK_FRAMES` frames into the track.
+pub const CHUNK_FRAMES: u64 = 4096;
+
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
 pub enum Request {
-    Play { track_id: Uuid }, // param uuid
+    /// Logs in as a user, needed first when the server has any configured.
+    Login(Credentials),
+    Play {
+        track_id: Uuid,
+    },
+    Prefetch {
+        track_id: Uuid,
+    },
+    /// Like Play, starting `position` seconds in.
+    Resume {
+        track_id: Uuid,
+        position: u32,
+    },
+    /// Like Play, starting at chunk `from_chunk`, to pick up a stream that
+    /// was cut off after the chunks before it arrived.
+    Continue {
+        track_id: Uuid,
+        from_chunk: u32,
+    },
+    /// Stops a stream the server announced in a `SongHeader`.
+    Stop {
+        stream_id: StreamId,
+    },
+    /// The client holds back the last `secs` of audio it receives, as it
+    /// does to crossfade, so its streams should run that much further ahead.
+    HoldBack {
+        secs: u32,
+    },
     Playlist(PlaylistRequest),
-    Meta { track_id: Uuid },
+    Meta {
+        track_id: Uuid,
+    },
     Search(SearchType),
+    Browse(BrowseRequest),
+    History(HistoryRequest),
+    /// Up to `count` tracks related to `seeds`, leaving out `exclude` and
+    /// anything played recently.
+    Radio {
+        seeds: Vec<Uuid>,
+        exclude: Vec<Uuid>,
+        count: usize,
+    },
+    Session(SessionRequest),
+    /// Makes this client controllable by `name`.
+    Register {
+        name: String,
+    },
+    /// Relays `command` to the client registered as `target`.
+    Control {
+        target: String,
+        command: Control,
+    },
+    Clients,
+    /// Operator requests, refused unless `token` matches the server's.
+    Admin {
+        token: String,
+        request: AdminRequest,
+    },
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
 pub enum Response {
     SongHeader {
+        stream_id: StreamId,
         track_id: Uuid,
         channels: u16,
         sample_rate: u32,
+        prefetch: bool,
+        /// Unix time in milliseconds at which the first chunk should play,
+        /// set for streams shared by a synced session.
+        start_at: Option<u64>,
+        /// Seconds into the track after which the server counts it as
+        /// played.
+        played_after: u32,
     },
     SongChunk {
+        stream_id: StreamId,
         track_id: Uuid,
         data: Vec<i16>,
+        /// counted from the start of the track, not of the stream
         index: u32,
     },
     EndOfStream {
+        stream_id: StreamId,
         track_id: Uuid,
     },
+    /// Sent ahead of chunk `index` of a synced stream: the chunk should
+    /// start playing at `at`, in unix milliseconds.
+    Timing {
+        stream_id: StreamId,
+        track_id: Uuid,
+        index: u32,
+        at: u64,
+    },
 
     Playlist(PlaylistResponse),
     SearchResults(Vec<SongMeta>),
+    Browse(BrowseResponse),
+    History(HistoryResponse),
+    Radio(Vec<SongMeta>),
+    Session(SessionResponse),
+    Clients(Vec<ClientInfo>),
+    Admin(AdminResponse),
+    LoggedIn {
+        user: String,
+        permission: Permission,
+    },
+    Denied(AuthError),
+    // a command relayed from the client registered as `from`
+    Control {
+        from: String,
+        command: Control,
+    },
     Meta(SongMeta),
     Error {
         message: String,
     },
+    /// The server is going away. Streams still running get a few seconds
+    /// to finish before the connection closes.
+    ShuttingDown,
 }
@@ -1,7 +1,20 @@
+mod admin;
+mod auth;
+mod browse;
+mod history;
 mod interface;
 mod playlists;
+mod query;
+mod remote;
+mod sessions;
 mod songs;
+pub use admin::*;
+pub use auth::*;
+pub use browse::*;
+pub use history::*;
 pub use interface::*;
 pub use playlists::*;
+pub use query::*;
+pub use remote::*;
+pub use sessions::*;
 pub use songs::*;
-
@@ -1,26 +1,138 @@
 use crate::SongMeta;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
 pub struct Playlist {
     pub name: String,
     pub len: usize,
+    pub query: Option<String>, // set for smart playlists
+    /// Whether other users can see it.
+    pub shared: bool,
+    /// Set for another user's shared playlist, which can be loaded and
+    /// exported as `owner/name` but not changed.
+    pub owner: Option<String>,
 }
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
 pub struct PlaylistMeta {
     pub title: String,
-    pub songs: Vec<SongMeta>,
+    pub entries: Vec<PlaylistEntry>,
+    #[serde(default)]
+    pub shared: bool,
+}
+
+/// A track reference in a playlist. The title and artists are a hint used to
+/// find the track again if its ID no longer resolves.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub struct PlaylistEntry {
+    pub id: Uuid,
+    pub title: String,
+    pub artists: Vec<String>,
+}
+
+impl From<&SongMeta> for PlaylistEntry {
+    fn from(song: &SongMeta) -> Self {
+        Self {
+            id: song.id,
+            title: song.title.clone(),
+            artists: song.artists.clone(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
 pub enum PlaylistRequest {
-    Get { name: String },
-    Create { name: String, songs: Vec<SongMeta> },
+    Get {
+        name: String,
+    },
+    Create {
+        name: String,
+        songs: Vec<Uuid>,
+    },
     List,
+    Rename {
+        name: String,
+        new_name: String,
+    },
+    Delete {
+        name: String,
+    },
+    AddSongs {
+        name: String,
+        songs: Vec<Uuid>,
+    },
+    // positions are 0-based
+    RemoveSongs {
+        name: String,
+        indices: Vec<usize>,
+    },
+    Move {
+        name: String,
+        from: usize,
+        to: usize,
+    },
+    Import {
+        name: String,
+        format: PlaylistFormat,
+        contents: String,
+    },
+    CreateSmart {
+        name: String,
+        query: String,
+    },
+    Export {
+        name: String,
+        format: PlaylistFormat,
+    },
+    /// Lets other users load the playlist, or stops them.
+    Share {
+        name: String,
+        shared: bool,
+    },
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
 pub enum PlaylistResponse {
     Playlists(Vec<Playlist>),
-    Songs(Vec<SongMeta>),
+    /// The songs found, and the entries that weren't. Each comes with its
+    /// position in the stored playlist, as `RemoveSongs` and `Move` take
+    /// it.
+    Songs {
+        songs: Vec<SongMeta>,
+        positions: Vec<usize>,
+        missing: Vec<(usize, PlaylistEntry)>,
+    },
+    Saved(Playlist),
+    Deleted {
+        name: String,
+    },
+    Imported {
+        playlist: Playlist,
+        unmatched: Vec<String>,
+    },
+    Exported {
+        name: String,
+        format: PlaylistFormat,
+        contents: String,
+    },
+}
+
+/// Playlist file formats used by other players. M3U covers `.m3u8` too, as
+/// contents are always UTF-8.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Eq)]
+pub enum PlaylistFormat {
+    M3u,
+    Pls,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "m3u" | "m3u8" => Some(PlaylistFormat::M3u),
+            "pls" => Some(PlaylistFormat::Pls),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            _ => None,
+        }
+    }
 }
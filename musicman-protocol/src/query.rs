@@ -0,0 +1,357 @@
+use std::{error::Error, fmt, str::FromStr};
+
+/// A filter expression over track fields and play statistics, such as
+/// `genre:jazz added:<30d plays:0` or `radiohead album:"ok computer" -live`.
+/// All filters must match.
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct Query {
+    pub filters: Vec<Filter>,
+}
+
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub enum Filter {
+    /// Free text looked up in the title, artists and album.
+    Words(String),
+    /// Matches when the inner filter doesn't, written with a leading `-`.
+    Not(Box<Filter>),
+    /// Case-insensitive substring match.
+    Text {
+        field: TextField,
+        value: String,
+    },
+    Number {
+        field: NumberField,
+        cmp: Comparison,
+    },
+    /// Compares how long ago something happened, in seconds.
+    Age {
+        field: AgeField,
+        cmp: Comparison,
+    },
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub enum TextField {
+    Title,
+    Artist,
+    Album,
+    Genre,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub enum NumberField {
+    Year,
+    Duration, // seconds
+    Plays,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub enum AgeField {
+    Added,
+    Played,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub enum Comparison {
+    Eq(u64),
+    Lt(u64),
+    Le(u64),
+    Gt(u64),
+    Ge(u64),
+    Between(u64, u64),
+}
+
+impl Comparison {
+    pub fn test(&self, value: u64) -> bool {
+        match *self {
+            Comparison::Eq(v) => value == v,
+            Comparison::Lt(v) => value < v,
+            Comparison::Le(v) => value <= v,
+            Comparison::Gt(v) => value > v,
+            Comparison::Ge(v) => value >= v,
+            Comparison::Between(lo, hi) => lo <= value && value <= hi,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct QueryError(pub String);
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for QueryError {}
+
+impl Query {
+    /// The free text words that aren't negated, joined by spaces.
+    pub fn words(&self) -> String {
+        self.filters
+            .iter()
+            .filter_map(|f| match f {
+                Filter::Words(w) => Some(w.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let filters = tokenize(s)?
+            .iter()
+            .map(parse_token)
+            .collect::<Result<Vec<Filter>, QueryError>>()?;
+
+        if filters.is_empty() {
+            return Err(QueryError("Empty query".to_string()));
+        }
+        Ok(Query { filters })
+    }
+}
+
+struct Token {
+    text: String,
+    negated: bool,
+    /// Started with a quote, so it's a phrase even if it contains `:`.
+    phrase: bool,
+}
+
+/// Splits on whitespace, keeping double-quoted runs together and dropping
+/// the quotes.
+fn tokenize(s: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut current: Option<Token> = None;
+    let mut quoted = false;
+
+    for c in s.chars() {
+        if c.is_whitespace() && !quoted {
+            tokens.extend(current.take());
+            continue;
+        }
+        let token = current.get_or_insert_with(|| Token {
+            text: String::new(),
+            negated: false,
+            phrase: false,
+        });
+        match c {
+            '-' if token.text.is_empty() && !token.negated && !token.phrase => token.negated = true,
+            '"' => {
+                token.phrase |= token.text.is_empty();
+                quoted = !quoted;
+            }
+            c => token.text.push(c),
+        }
+    }
+    if quoted {
+        return Err(QueryError("Unterminated quote".to_string()));
+    }
+    tokens.extend(current);
+    Ok(tokens)
+}
+
+fn parse_token(token: &Token) -> Result<Filter, QueryError> {
+    if token.text.is_empty() {
+        return Err(QueryError("Nothing to match after `-`".to_string()));
+    }
+    let filter = match token.text.split_once(':') {
+        Some((field, value)) if !token.phrase => parse_filter(field, value)?,
+        _ => Filter::Words(token.text.to_lowercase()),
+    };
+    Ok(match token.negated {
+        true => Filter::Not(Box::new(filter)),
+        false => filter,
+    })
+}
+
+fn parse_filter(field: &str, value: &str) -> Result<Filter, QueryError> {
+    if value.is_empty() {
+        return Err(QueryError(format!("Missing value for `{field}`")));
+    }
+
+    let text = |field| {
+        Ok(Filter::Text {
+            field,
+            value: value.to_lowercase(),
+        })
+    };
+    let number = |field, unit: fn(&str) -> Option<u64>| {
+        Ok(Filter::Number {
+            field,
+            cmp: parse_comparison(value, unit)?,
+        })
+    };
+    let age = |field| {
+        Ok(Filter::Age {
+            field,
+            cmp: parse_comparison(value, parse_age)?,
+        })
+    };
+
+    match field.to_lowercase().as_str() {
+        "title" => text(TextField::Title),
+        "artist" => text(TextField::Artist),
+        "album" => text(TextField::Album),
+        "genre" => text(TextField::Genre),
+        "year" => number(NumberField::Year, parse_plain),
+        "duration" => number(NumberField::Duration, parse_duration),
+        "plays" => number(NumberField::Plays, parse_plain),
+        "added" => age(AgeField::Added),
+        "played" => age(AgeField::Played),
+        _ => Err(QueryError(format!("Unknown field `{field}`"))),
+    }
+}
+
+fn parse_comparison(value: &str, unit: fn(&str) -> Option<u64>) -> Result<Comparison, QueryError> {
+    let parse = |v: &str| unit(v).ok_or_else(|| QueryError(format!("Invalid value `{v}`")));
+
+    if let Some((lo, hi)) = value.split_once("..") {
+        return Ok(Comparison::Between(parse(lo)?, parse(hi)?));
+    }
+    if let Some(v) = value.strip_prefix(">=") {
+        return Ok(Comparison::Ge(parse(v)?));
+    }
+    if let Some(v) = value.strip_prefix("<=") {
+        return Ok(Comparison::Le(parse(v)?));
+    }
+    if let Some(v) = value.strip_prefix('>') {
+        return Ok(Comparison::Gt(parse(v)?));
+    }
+    if let Some(v) = value.strip_prefix('<') {
+        return Ok(Comparison::Lt(parse(v)?));
+    }
+    Ok(Comparison::Eq(parse(
+        value.strip_prefix('=').unwrap_or(value),
+    )?))
+}
+
+fn parse_plain(v: &str) -> Option<u64> {
+    v.parse().ok()
+}
+
+/// Seconds from `90`, `90s`, `5m` or `1h`.
+fn parse_duration(v: &str) -> Option<u64> {
+    parse_with_units(v, &[("s", 1), ("m", 60), ("h", 3600)])
+}
+
+/// Seconds from `30`, `30d`, `12h`, `2w` or `1y`. Plain numbers are days.
+pub fn parse_age(v: &str) -> Option<u64> {
+    parse_with_units(
+        v,
+        &[
+            ("d", 86400),
+            ("h", 3600),
+            ("w", 7 * 86400),
+            ("y", 365 * 86400),
+        ],
+    )
+}
+
+fn parse_with_units(v: &str, units: &[(&str, u64)]) -> Option<u64> {
+    if let Ok(n) = v.parse::<u64>() {
+        return n.checked_mul(units[0].1);
+    }
+    units.iter().find_map(|(suffix, scale)| {
+        v.strip_suffix(suffix)
+            .and_then(|n| n.parse::<u64>().ok())
+            .and_then(|n| n.checked_mul(*scale))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Vec<Filter> {
+        s.parse::<Query>().unwrap().filters
+    }
+
+    #[test]
+    fn ranges_and_comparisons() {
+        assert_eq!(
+            parse("year:1990..1999 plays:>=3 duration:<5m"),
+            vec![
+                Filter::Number {
+                    field: NumberField::Year,
+                    cmp: Comparison::Between(1990, 1999),
+                },
+                Filter::Number {
+                    field: NumberField::Plays,
+                    cmp: Comparison::Ge(3),
+                },
+                Filter::Number {
+                    field: NumberField::Duration,
+                    cmp: Comparison::Lt(300),
+                },
+            ]
+        );
+        assert_eq!(
+            parse("added:<2w played:=1y"),
+            vec![
+                Filter::Age {
+                    field: AgeField::Added,
+                    cmp: Comparison::Lt(14 * 86400),
+                },
+                Filter::Age {
+                    field: AgeField::Played,
+                    cmp: Comparison::Eq(365 * 86400),
+                },
+            ]
+        );
+        assert!("year:1990..".parse::<Query>().is_err());
+        assert!("duration:5x".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(
+            parse(r#"album:"OK Computer" "live: at leeds""#),
+            vec![
+                Filter::Text {
+                    field: TextField::Album,
+                    value: "ok computer".to_string(),
+                },
+                Filter::Words("live: at leeds".to_string()),
+            ]
+        );
+        assert!(r#"album:"ok computer"#.parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn negation() {
+        assert_eq!(
+            parse(r#"radiohead -live -genre:jazz -"b sides""#),
+            vec![
+                Filter::Words("radiohead".to_string()),
+                Filter::Not(Box::new(Filter::Words("live".to_string()))),
+                Filter::Not(Box::new(Filter::Text {
+                    field: TextField::Genre,
+                    value: "jazz".to_string(),
+                })),
+                Filter::Not(Box::new(Filter::Words("b sides".to_string()))),
+            ]
+        );
+        assert_eq!(parse("co-op"), vec![Filter::Words("co-op".to_string())]);
+        assert!("-".parse::<Query>().is_err());
+        assert!("".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn overflowing_numbers() {
+        assert!("added:<999999999999999y".parse::<Query>().is_err());
+        assert!("duration:>999999999999999999m".parse::<Query>().is_err());
+        assert!("played:99999999999999999999".parse::<Query>().is_err());
+        assert_eq!(
+            parse("duration:>3000000000m"),
+            vec![Filter::Number {
+                field: NumberField::Duration,
+                cmp: Comparison::Gt(180_000_000_000),
+            }]
+        );
+    }
+}
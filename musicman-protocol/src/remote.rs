@@ -0,0 +1,21 @@
+use crate::SongMeta;
+use serde::{Deserialize, Serialize};
+
+/// A playback command for another client, relayed by the server.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub enum Control {
+    Pause,
+    Resume,
+    Next,
+    Prev,
+    Enqueue(Vec<SongMeta>),
+    // percent
+    SetVolume(u8),
+}
+
+/// A connected client that can be controlled by `name`.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub struct ClientInfo {
+    pub name: String,
+    pub addr: String,
+}
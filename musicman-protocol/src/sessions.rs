@@ -0,0 +1,61 @@
+use crate::SongMeta;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub enum SessionRequest {
+    /// Joins the named session, creating it if needed.
+    Attach {
+        name: String,
+    },
+    Detach,
+    Update(SessionUpdate),
+    /// Turns synced playback on or off for the attached session. While on,
+    /// the server streams the session queue to every attached client.
+    Sync(bool),
+    List,
+}
+
+/// Playback modes kept with a session.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq, Default)]
+pub struct SessionModes {
+    pub radio: bool,
+    // crossfade length and curve name, off when `None`
+    pub crossfade: Option<(u32, String)>,
+}
+
+/// What the attached client is playing. `position` is in seconds.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub struct SessionUpdate {
+    pub queue: Vec<Uuid>,
+    pub current_idx: usize,
+    pub position: u32,
+    pub modes: SessionModes,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub struct SessionState {
+    pub name: String,
+    pub queue: Vec<SongMeta>,
+    pub current_idx: usize,
+    pub position: u32,
+    pub modes: SessionModes,
+    pub sync: bool,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub struct SessionSummary {
+    pub name: String,
+    pub len: usize,
+    pub clients: usize,
+    pub sync: bool,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
+pub enum SessionResponse {
+    Attached(SessionState),
+    Detached { name: String },
+    // sent to every client in the session when synced playback is toggled
+    Synced { name: String, on: bool },
+    Sessions(Vec<SessionSummary>),
+}
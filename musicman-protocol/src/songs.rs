@@ -7,12 +7,28 @@ pub struct SongMeta {
     pub id: Uuid,
     pub title: String,
     pub artists: Vec<String>,
+    #[serde(default)]
+    pub album: String,
+    #[serde(default)]
+    pub album_artist: String,
+    #[serde(default)]
+    pub genre: String,
+    #[serde(default)]
+    pub year: Option<u32>,
+    #[serde(default)]
+    pub disc: Option<u32>,
+    #[serde(default)]
+    pub track: Option<u32>,
     pub duration: u32, // in seconds
     pub path: PathBuf,
+    #[serde(default)]
+    pub added: u64, // unix time the track was first indexed
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Eq)]
 pub enum SearchType {
     ByTitle(String),
     ByArtist(String),
+    /// A [`Query`](crate::Query) expression, checked with `str::parse` first.
+    Query(String),
 }
@@ -0,0 +1,148 @@
+use crate::{auth::same_secret, config::AdminConfig, counters, helpers::*, types::*};
+use musicman_protocols::*;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use tokio::sync::mpsc;
+
+/// Files read between progress reports during a rescan.
+const PROGRESS_EVERY: usize = 50;
+
+static RESCANNING: AtomicBool = AtomicBool::new(false);
+
+/// Whether `token` is the configured admin token. Without one, only users
+/// with the admin permission can send admin requests.
+fn authorized(token: &str, config: &AdminConfig) -> bool {
+    config
+        .token
+        .as_deref()
+        .is_some_and(|expected| same_secret(token, expected))
+}
+
+/// `user` is who the client logged in as; admin users need no token.
+pub async fn handle_admin(
+    token: String,
+    req: AdminRequest,
+    user: Option<&User>,
+    ctx: &Arc<Context>,
+    write: &WriteSocket,
+) -> anyhow::Result<()> {
+    let admin_user = user.is_some_and(|u| u.permission == Permission::Admin);
+    let config = ctx.config();
+    if !admin_user && !authorized(&token, &config.admin) {
+        let message = match config.admin.token {
+            Some(_) => "Wrong admin token",
+            None => "Admin requests are disabled on this server",
+        };
+        tracing::warn!("Refused an admin request: {message}.");
+        let res = Response::Error {
+            message: message.to_string(),
+        };
+        return send_to_client(write, &res).await;
+    }
+
+    let res = match req {
+        AdminRequest::Rescan { full } => return rescan(full, ctx, write).await,
+        AdminRequest::Stats => Ok(AdminResponse::Stats(stats(ctx).await)),
+        AdminRequest::Clients => Ok(AdminResponse::Clients(clients(ctx).await)),
+        AdminRequest::Disconnect { addr } => match ctx.connections.lock().await.remove(&addr) {
+            Some(conn) => {
+                tracing::info!("Disconnecting {addr} for an admin.");
+                let _ = conn.kick.try_send(());
+                Ok(AdminResponse::Disconnected { addr })
+            }
+            None => Err(format!("No client connected from {addr}")),
+        },
+    };
+    let res = match res {
+        Ok(res) => Response::Admin(res),
+        Err(message) => Response::Error { message },
+    };
+    send_to_client(write, &res).await
+}
+
+/// Re-reads the music directory, reporting progress as it goes, and swaps
+/// the new index in.
+async fn rescan(full: bool, ctx: &Context, write: &WriteSocket) -> anyhow::Result<()> {
+    if RESCANNING.swap(true, Ordering::SeqCst) {
+        let res = Response::Error {
+            message: "A rescan is already running".to_string(),
+        };
+        return send_to_client(write, &res).await;
+    }
+
+    let (progress_tx, mut progress_rx) = mpsc::unbounded_channel();
+    let scan = tokio::task::spawn_blocking(move || {
+        generate_index(&music_dir(), !full, |done, total| {
+            if done % PROGRESS_EVERY == 0 || done == total {
+                let _ = progress_tx.send((done, total));
+            }
+        })
+    });
+    while let Some((done, total)) = progress_rx.recv().await {
+        let res = Response::Admin(AdminResponse::Progress { done, total });
+        // the scan carries on even if the admin went away
+        let _ = send_to_client(write, &res).await;
+    }
+
+    let scanned = scan.await;
+    RESCANNING.store(false, Ordering::SeqCst);
+    let index = match scanned? {
+        Ok(index) => index,
+        Err(e) => {
+            let res = Response::Error {
+                message: format!("Rescan failed: {e}"),
+            };
+            return send_to_client(write, &res).await;
+        }
+    };
+
+    let old = ctx.library();
+    let old = old.songs();
+    let added = index.keys().filter(|id| !old.contains_key(id)).count();
+    let removed = old.keys().filter(|id| !index.contains_key(id)).count();
+    let songs = index.len();
+    ctx.set_library(Library::new(index));
+
+    tracing::info!("Rescanned: {songs} songs, {added} added, {removed} removed.");
+    let res = Response::Admin(AdminResponse::Rescanned {
+        songs,
+        added,
+        removed,
+    });
+    send_to_client(write, &res).await
+}
+
+async fn stats(ctx: &Context) -> ServerStats {
+    ServerStats {
+        songs: ctx.library().songs().len(),
+        uptime: ctx.started.elapsed().as_secs(),
+        clients: ctx.connections.lock().await.len(),
+        active_streams: counters::active_streams(),
+        bytes_sent: counters::bytes_sent(),
+    }
+}
+
+async fn clients(ctx: &Context) -> Vec<ConnectedClient> {
+    let library = ctx.library();
+    let index = library.songs();
+    let mut clients: Vec<ConnectedClient> = ctx
+        .connections
+        .lock()
+        .await
+        .iter()
+        .map(|(addr, c)| ConnectedClient {
+            addr: addr.clone(),
+            name: c.name.clone(),
+            session: c.session.clone(),
+            track: c
+                .track
+                .and_then(|id| index.get(&id))
+                .map(|song| (song.id, song.title.clone())),
+            connected: c.connected,
+        })
+        .collect();
+    clients.sort_by_key(|c| c.connected);
+    clients
+}
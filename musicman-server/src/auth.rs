@@ -0,0 +1,113 @@
+use crate::{config::AuthConfig, types::User};
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use musicman_protocols::*;
+use std::sync::LazyLock;
+
+/// Failed logins allowed on one connection before the server hangs up.
+pub const MAX_LOGIN_ATTEMPTS: u32 = 3;
+
+/// Checked instead when a user has no password, so that logging in as a
+/// user who doesn't exist takes as long as with a wrong password.
+static DUMMY_HASH: LazyLock<String> =
+    LazyLock::new(|| hash_password("musicman").unwrap_or_default());
+
+/// Makes the hash checked for unknown users ahead of any login.
+pub fn prepare() {
+    LazyLock::force(&DUMMY_HASH);
+}
+
+/// Whether `a` and `b` are equal, compared in full so the time taken
+/// doesn't tell how much matched.
+pub fn same_secret(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+/// The configured user `credentials` belong to.
+pub async fn login(credentials: Credentials, config: &AuthConfig) -> Result<User, AuthError> {
+    let found = match credentials {
+        Credentials::Token(token) => config.users.iter().find(|user| {
+            user.token
+                .as_deref()
+                .is_some_and(|expected| same_secret(&token, expected))
+        }),
+        Credentials::Password { user, password } => {
+            let found = config.users.iter().find(|u| u.name == user);
+            let hash = found.and_then(|u| u.password.clone());
+            // argon2 is slow on purpose, so keep it off the runtime threads
+            let verified = tokio::task::spawn_blocking(move || match hash {
+                Some(hash) => verify_password(&password, &hash),
+                None => {
+                    verify_password(&password, &DUMMY_HASH);
+                    false
+                }
+            })
+            .await;
+            found.filter(|_| verified.unwrap_or(false))
+        }
+    };
+
+    found
+        .map(|user| User {
+            name: user.name.clone(),
+            permission: user.permission,
+        })
+        .ok_or(AuthError::InvalidCredentials)
+}
+
+/// The permission `request` needs, or `None` if it can be sent without
+/// logging in.
+fn needs(request: &Request) -> Option<Permission> {
+    match request {
+        // admin requests carry their own token
+        Request::Login(_) | Request::Admin { .. } => None,
+        Request::Playlist(
+            PlaylistRequest::Get { .. } | PlaylistRequest::List | PlaylistRequest::Export { .. },
+        ) => Some(Permission::ReadOnly),
+        Request::Playlist(_) => Some(Permission::PlaylistWrite),
+        _ => Some(Permission::ReadOnly),
+    }
+}
+
+/// Refuses `request` when the server has users and `user` isn't one allowed
+/// to send it.
+pub fn check(request: &Request, user: Option<&User>, config: &AuthConfig) -> Result<(), AuthError> {
+    let Some(needs) = needs(request) else {
+        return Ok(());
+    };
+    if config.users.is_empty() {
+        return Ok(());
+    }
+    match user {
+        None => Err(AuthError::Required),
+        Some(user) if user.permission < needs => Err(AuthError::Forbidden { needs }),
+        Some(_) => Ok(()),
+    }
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(e) => {
+            tracing::warn!("Ignoring a malformed password hash in server.toml: {e}");
+            false
+        }
+    }
+}
+
+/// A hash of `password` for a user's `password` in `server.toml`.
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| anyhow::anyhow!("Could not hash password: {e}"))
+}
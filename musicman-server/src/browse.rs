@@ -0,0 +1,83 @@
+use crate::{search::normalize, types::*};
+use musicman_protocols::*;
+use std::collections::BTreeMap;
+
+/// Counts tracks per key, skipping tracks without one. Keys that only differ
+/// in case or accents are merged under the first spelling seen.
+fn group<'a, F, I>(songs: &'a SongIndex, keys: F) -> Vec<BrowseGroup>
+where
+    F: Fn(&'a SongMeta) -> I,
+    I: IntoIterator<Item = String>,
+{
+    let mut groups: BTreeMap<String, BrowseGroup> = BTreeMap::new();
+    for song in songs.values() {
+        for key in keys(song) {
+            if key.is_empty() {
+                continue;
+            }
+            groups
+                .entry(normalize(&key))
+                .or_insert(BrowseGroup {
+                    name: key,
+                    tracks: 0,
+                })
+                .tracks += 1;
+        }
+    }
+    groups.into_values().collect()
+}
+
+pub fn artists(songs: &SongIndex) -> Vec<BrowseGroup> {
+    group(songs, |song| song.artists.clone())
+}
+
+/// Albums with a track by an artist named `artist`, or failing an exact
+/// name, an artist containing it.
+pub fn albums(songs: &SongIndex, artist: &str) -> Vec<BrowseGroup> {
+    let wanted = normalize(artist);
+    let by = |song: &SongMeta, exact: bool| {
+        song.artists.iter().any(|a| {
+            let a = normalize(a);
+            if exact {
+                a == wanted
+            } else {
+                a.contains(&wanted)
+            }
+        })
+    };
+    let exact = songs.values().any(|song| by(song, true));
+
+    group(songs, |song| by(song, exact).then(|| song.album.clone()))
+}
+
+/// Tracks on albums named `name` in disc and track order, or failing an
+/// exact name, albums containing it.
+pub fn album(songs: &SongIndex, name: &str) -> Vec<SongMeta> {
+    let wanted = normalize(name);
+    let mut tracks: Vec<SongMeta> = songs
+        .values()
+        .filter(|song| normalize(&song.album) == wanted)
+        .cloned()
+        .collect();
+    if tracks.is_empty() {
+        tracks = songs
+            .values()
+            .filter(|song| !song.album.is_empty() && normalize(&song.album).contains(&wanted))
+            .cloned()
+            .collect();
+    }
+
+    tracks.sort_by(|a, b| {
+        (&a.album, a.disc, a.track, &a.title).cmp(&(&b.album, b.disc, b.track, &b.title))
+    });
+    tracks
+}
+
+pub fn genres(songs: &SongIndex) -> Vec<BrowseGroup> {
+    group(songs, |song| Some(song.genre.clone()))
+}
+
+pub fn years(songs: &SongIndex) -> Vec<BrowseGroup> {
+    // zero padded so they sort as numbers
+    group(songs, |song| song.year.map(|y| format!("{y:04}")))
+}
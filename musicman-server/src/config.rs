@@ -0,0 +1,145 @@
+use crate::types::Context;
+use anyhow::Result;
+use musicman_protocols::Permission;
+use serde::Deserialize;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use tokio::signal::unix::{SignalKind, signal};
+
+/// Settings read from `server.toml` in the musicman config directory. Every
+/// field is optional.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    pub history: HistoryConfig,
+    pub stream: StreamConfig,
+    pub admin: AdminConfig,
+    pub auth: AuthConfig,
+    pub tls: Option<TlsConfig>,
+    pub listen: ListenConfig,
+    pub metrics: Option<MetricsConfig>,
+}
+
+/// What to listen on. The TCP port comes from the command line.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ListenConfig {
+    pub tcp: bool,
+    pub unix: Option<PathBuf>,
+    /// Permissions for the socket file, e.g. `0o660`.
+    pub unix_mode: Option<u32>,
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        ListenConfig {
+            tcp: true,
+            unix: None,
+            unix_mode: None,
+        }
+    }
+}
+
+/// PEM files to serve TLS with. While set, plain connections are only
+/// accepted from localhost.
+#[derive(Deserialize, Clone, Debug)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Where to serve metrics in Prometheus' text format. Anyone who can
+/// reach the address can read them, so it's best kept on localhost.
+#[derive(Deserialize, Clone, Debug)]
+pub struct MetricsConfig {
+    pub listen: SocketAddr,
+}
+
+/// With any users set, clients have to log in as one of them before the
+/// server does anything for them.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub users: Vec<UserConfig>,
+}
+
+/// A user logs in with `token`, or with their name and the password
+/// `password` is an argon2 hash of.
+#[derive(Deserialize, Clone, Debug)]
+pub struct UserConfig {
+    pub name: String,
+    pub token: Option<String>,
+    pub password: Option<String>,
+    #[serde(default)]
+    pub permission: Permission,
+}
+
+/// Admin requests are refused unless a token is set and they carry it.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct AdminConfig {
+    pub token: Option<String>,
+}
+
+/// A stream counts as a play once it gets `threshold_percent` of the way
+/// through the track or `threshold_secs` in, whichever comes first.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct HistoryConfig {
+    pub threshold_percent: u32,
+    pub threshold_secs: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            threshold_percent: 50,
+            threshold_secs: 240,
+        }
+    }
+}
+
+/// Streams are paced to playback, running at most `lead_secs` of audio
+/// ahead of it. That much goes out at once when a stream starts, so
+/// playback can begin right away.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct StreamConfig {
+    pub lead_secs: u64,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        StreamConfig { lead_secs: 10 }
+    }
+}
+
+pub async fn load_config() -> Result<Config> {
+    let config_file = dirs::config_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?
+        .join("musicman")
+        .join("server.toml");
+    match tokio::fs::read_to_string(&config_file).await {
+        Ok(data) => Ok(toml::from_str(&data)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Loads the config again on every SIGHUP. What to listen on, TLS and
+/// metrics are only set up at startup, so changes to those wait for a
+/// restart.
+pub fn reload_on_hangup(ctx: Arc<Context>) -> Result<()> {
+    let mut hangups = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            match load_config().await {
+                Ok(config) => {
+                    ctx.set_config(config);
+                    tracing::info!("Reloaded the config.");
+                }
+                Err(e) => tracing::error!("Could not reload the config: {e:#}"),
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use musicman_protocols::Request;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+static ACTIVE_STREAMS: AtomicUsize = AtomicUsize::new(0);
+
+/// Bytes written to clients since the server started.
+pub fn bytes_sent() -> u64 {
+    BYTES_SENT.load(Ordering::Relaxed)
+}
+
+pub fn count_sent(bytes: usize) {
+    BYTES_SENT.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+pub fn active_streams() -> usize {
+    ACTIVE_STREAMS.load(Ordering::Relaxed)
+}
+
+/// Counts a stream as active for as long as it's held.
+pub struct ActiveStream;
+
+impl ActiveStream {
+    pub fn start() -> Self {
+        ACTIVE_STREAMS.fetch_add(1, Ordering::Relaxed);
+        ActiveStream
+    }
+}
+
+impl Drop for ActiveStream {
+    fn drop(&mut self) {
+        ACTIVE_STREAMS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+static STREAMED_BYTES: AtomicU64 = AtomicU64::new(0);
+static DECODE_ERRORS: AtomicU64 = AtomicU64::new(0);
+static SCAN_MILLIS: AtomicU64 = AtomicU64::new(0);
+static REQUESTS: LazyLock<Mutex<BTreeMap<&'static str, u64>>> = LazyLock::new(Default::default);
+
+/// Upper bounds of the search latency buckets, in seconds.
+pub const SEARCH_BUCKETS: [f64; 8] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25];
+// searches per bucket, the last one for anything slower
+static SEARCHES: [AtomicU64; SEARCH_BUCKETS.len() + 1] =
+    [const { AtomicU64::new(0) }; SEARCH_BUCKETS.len() + 1];
+static SEARCH_MICROS: AtomicU64 = AtomicU64::new(0);
+
+/// Bytes of audio sent in stream chunks, a part of `bytes_sent`.
+pub fn streamed_bytes() -> u64 {
+    STREAMED_BYTES.load(Ordering::Relaxed)
+}
+
+pub fn count_streamed(bytes: usize) {
+    STREAMED_BYTES.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+/// Packets that failed to decode and were skipped.
+pub fn decode_errors() -> u64 {
+    DECODE_ERRORS.load(Ordering::Relaxed)
+}
+
+pub fn count_decode_error() {
+    DECODE_ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// How long the last scan of the music directory took.
+pub fn scan_duration() -> Duration {
+    Duration::from_millis(SCAN_MILLIS.load(Ordering::Relaxed))
+}
+
+pub fn set_scan_duration(duration: Duration) {
+    SCAN_MILLIS.store(duration.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Requests received so far by type.
+pub fn requests() -> BTreeMap<&'static str, u64> {
+    REQUESTS.lock().unwrap().clone()
+}
+
+pub fn count_request(request: &Request) {
+    let kind = match request {
+        Request::Login(_) => "login",
+        Request::Play { .. } => "play",
+        Request::Prefetch { .. } => "prefetch",
+        Request::Resume { .. } => "resume",
+        Request::Continue { .. } => "continue",
+        Request::Stop { .. } => "stop",
+        Request::HoldBack { .. } => "hold_back",
+        Request::Playlist(_) => "playlist",
+        Request::Meta { .. } => "meta",
+        Request::Search(_) => "search",
+        Request::Browse(_) => "browse",
+        Request::History(_) => "history",
+        Request::Radio { .. } => "radio",
+        Request::Session(_) => "session",
+        Request::Register { .. } => "register",
+        Request::Control { .. } => "control",
+        Request::Clients => "clients",
+        Request::Admin { .. } => "admin",
+    };
+    *REQUESTS.lock().unwrap().entry(kind).or_default() += 1;
+}
+
+/// Searches per latency bucket, not cumulative, and the total time they
+/// took.
+pub fn searches() -> (Vec<u64>, Duration) {
+    let counts = SEARCHES.iter().map(|c| c.load(Ordering::Relaxed)).collect();
+    let total = Duration::from_micros(SEARCH_MICROS.load(Ordering::Relaxed));
+    (counts, total)
+}
+
+pub fn observe_search(took: Duration) {
+    let secs = took.as_secs_f64();
+    let bucket = SEARCH_BUCKETS
+        .iter()
+        .position(|bound| secs <= *bound)
+        .unwrap_or(SEARCH_BUCKETS.len());
+    SEARCHES[bucket].fetch_add(1, Ordering::Relaxed);
+    SEARCH_MICROS.fetch_add(took.as_micros() as u64, Ordering::Relaxed);
+}
@@ -0,0 +1,483 @@
+use crate::{search::normalize, types::*};
+use anyhow::Result;
+use musicman_protocols::*;
+use quick_xml::{Reader, escape::escape, events::Event};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    path::{Component, Path, PathBuf},
+};
+
+/// An entry read from another player's playlist, before it's matched
+/// against the index.
+#[derive(Default)]
+pub struct ForeignEntry {
+    pub location: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+}
+
+impl ForeignEntry {
+    fn describe(&self) -> String {
+        match (&self.artist, &self.title, &self.location) {
+            (Some(artist), Some(title), _) => format!("{artist} - {title}"),
+            (None, Some(title), _) => title.clone(),
+            (_, None, Some(location)) => location.clone(),
+            _ => "Unknown entry".to_string(),
+        }
+    }
+
+    /// Title and artist from the tags, or failing that from an
+    /// "Artist - Title" style file name.
+    fn tags(&self) -> (Option<String>, Option<String>) {
+        if self.title.is_some() {
+            return (self.artist.clone(), self.title.clone());
+        }
+        let stem = self
+            .location
+            .as_deref()
+            .map(location_path)
+            .and_then(|p| p.file_stem().map(|s| s.to_string_lossy().to_string()));
+        match stem {
+            Some(stem) => split_display(&stem),
+            None => (None, None),
+        }
+    }
+}
+
+pub fn parse(format: PlaylistFormat, contents: &str) -> Result<Vec<ForeignEntry>> {
+    match format {
+        PlaylistFormat::M3u => Ok(parse_m3u(contents)),
+        PlaylistFormat::Pls => Ok(parse_pls(contents)),
+        PlaylistFormat::Xspf => parse_xspf(contents),
+    }
+}
+
+pub fn export(format: PlaylistFormat, title: &str, songs: &[SongMeta]) -> String {
+    match format {
+        PlaylistFormat::M3u => export_m3u(title, songs),
+        PlaylistFormat::Pls => export_pls(songs),
+        PlaylistFormat::Xspf => export_xspf(title, songs),
+    }
+}
+
+/// Matches entries to indexed tracks. Paths are tried first, exactly and
+/// then by their trailing components so libraries that moved still match,
+/// then the title and artist. Returns the matched tracks and descriptions
+/// of the entries that couldn't be found.
+pub fn resolve(entries: &[ForeignEntry], index: &SongIndex) -> (Vec<SongMeta>, Vec<String>) {
+    let by_path: HashMap<&Path, &SongMeta> =
+        index.values().map(|s| (s.path.as_path(), s)).collect();
+    let mut songs = Vec::new();
+    let mut unmatched = Vec::new();
+
+    for entry in entries {
+        let path = entry.location.as_deref().map(location_path);
+        let exact = path.as_deref().and_then(|p| by_path.get(p).copied());
+        let suffix = path.as_deref().and_then(|p| find_by_suffix(p, index));
+
+        let found = exact
+            .or(suffix.filter(|(depth, _)| *depth > 1).map(|(_, s)| s))
+            .or_else(|| find_by_tags(entry, index))
+            .or(suffix.map(|(_, s)| s));
+
+        match found {
+            Some(song) => songs.push(song.clone()),
+            None => unmatched.push(entry.describe()),
+        }
+    }
+
+    (songs, unmatched)
+}
+
+/// The indexed track sharing the most trailing path components with `path`,
+/// along with how many matched. The file name must match at least.
+fn find_by_suffix<'a>(path: &Path, index: &'a SongIndex) -> Option<(usize, &'a SongMeta)> {
+    let wanted: Vec<String> = path_components(path);
+
+    index
+        .values()
+        .map(|song| {
+            let depth = path_components(&song.path)
+                .iter()
+                .rev()
+                .zip(wanted.iter().rev())
+                .take_while(|(a, b)| a == b)
+                .count();
+            (depth, song)
+        })
+        .filter(|(depth, _)| *depth > 0)
+        .max_by_key(|(depth, _)| *depth)
+}
+
+fn path_components(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy().to_lowercase()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn find_by_tags<'a>(entry: &ForeignEntry, index: &'a SongIndex) -> Option<&'a SongMeta> {
+    let (artist, title) = entry.tags();
+    let title = normalize(&title?);
+    let credited = artist.as_deref().map(credited);
+
+    index.values().find(|song| {
+        normalize(&song.title) == title
+            && credited.as_ref().is_none_or(|credited| {
+                credited.contains(&normalize(&song.artists.join(", ")))
+                    || song
+                        .artists
+                        .iter()
+                        .any(|a| credited.contains(&normalize(a)))
+            })
+    })
+}
+
+/// The names an artist field may credit: the whole field, and each artist
+/// it lists.
+fn credited(artist: &str) -> Vec<String> {
+    let mut listed = artist.to_lowercase();
+    for separator in [
+        " featuring ",
+        " feat. ",
+        " feat ",
+        " ft. ",
+        ",",
+        ";",
+        "&",
+        "/",
+    ] {
+        listed = listed.replace(separator, "\0");
+    }
+    let mut names = vec![normalize(artist)];
+    names.extend(listed.split('\0').map(normalize).filter(|n| !n.is_empty()));
+    names
+}
+
+/// Splits an "Artist - Title" display string.
+fn split_display(display: &str) -> (Option<String>, Option<String>) {
+    match display.split_once(" - ") {
+        Some((artist, title)) => (
+            Some(artist.trim().to_string()),
+            Some(title.trim().to_string()),
+        ),
+        None => (None, Some(display.trim().to_string())),
+    }
+}
+
+/// Turns a playlist location, either a plain path or a `file://` URL, into a
+/// path.
+fn location_path(location: &str) -> PathBuf {
+    let path = match location.strip_prefix("file://") {
+        Some(rest) => percent_decode(rest.strip_prefix("localhost").unwrap_or(rest)),
+        None => location.to_string(),
+    };
+    PathBuf::from(path.replace('\\', "/"))
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+fn file_url(path: &Path) -> String {
+    let mut url = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                url.push(byte as char)
+            }
+            _ => {
+                let _ = write!(url, "%{byte:02X}");
+            }
+        }
+    }
+    url
+}
+
+fn display_name(song: &SongMeta) -> String {
+    format!("{} - {}", song.artists.join(", "), song.title)
+}
+
+fn parse_m3u(contents: &str) -> Vec<ForeignEntry> {
+    let mut entries = Vec::new();
+    let mut info = (None, None);
+
+    for line in contents.lines() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        if line.is_empty() {
+            continue;
+        }
+        // #EXTINF:<seconds> [attributes],<display name>
+        if let Some(rest) = line.strip_prefix("#EXTINF:") {
+            info = rest
+                .split_once(',')
+                .map(|(_, display)| split_display(display))
+                .unwrap_or((None, None));
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let (artist, title) = std::mem::take(&mut info);
+        entries.push(ForeignEntry {
+            location: Some(line.to_string()),
+            title,
+            artist,
+        });
+    }
+
+    entries
+}
+
+fn parse_pls(contents: &str) -> Vec<ForeignEntry> {
+    let mut entries: BTreeMap<u32, ForeignEntry> = BTreeMap::new();
+
+    for line in contents.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim().to_string();
+
+        if let Some(n) = key.strip_prefix("file").and_then(|n| n.parse().ok()) {
+            entries.entry(n).or_default().location = Some(value);
+        } else if let Some(n) = key.strip_prefix("title").and_then(|n| n.parse().ok()) {
+            let (artist, title) = split_display(&value);
+            let entry = entries.entry(n).or_default();
+            entry.artist = artist;
+            entry.title = title;
+        }
+    }
+
+    entries
+        .into_values()
+        .filter(|e| e.location.is_some())
+        .collect()
+}
+
+fn parse_xspf(contents: &str) -> Result<Vec<ForeignEntry>> {
+    let mut reader = Reader::from_str(contents);
+    reader.config_mut().trim_text(true);
+
+    let mut entries = Vec::new();
+    let mut track: Option<ForeignEntry> = None;
+    let mut element = String::new();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                element = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                if element == "track" {
+                    track = Some(ForeignEntry::default());
+                }
+            }
+            Event::End(e) => {
+                if e.local_name().as_ref() == b"track"
+                    && let Some(t) = track.take()
+                {
+                    entries.push(t);
+                }
+                element.clear();
+            }
+            Event::Text(e) => {
+                let text = e.unescape()?.to_string();
+                if let Some(t) = track.as_mut() {
+                    match element.as_str() {
+                        "location" if t.location.is_none() => t.location = Some(text),
+                        "title" => t.title = Some(text),
+                        "creator" => t.artist = Some(text),
+                        _ => {}
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(entries)
+}
+
+fn export_m3u(title: &str, songs: &[SongMeta]) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    let _ = writeln!(out, "#PLAYLIST:{title}");
+    for song in songs {
+        let _ = writeln!(out, "#EXTINF:{},{}", song.duration, display_name(song));
+        let _ = writeln!(out, "{}", song.path.display());
+    }
+    out
+}
+
+fn export_pls(songs: &[SongMeta]) -> String {
+    let mut out = String::from("[playlist]\n");
+    for (i, song) in songs.iter().enumerate() {
+        let n = i + 1;
+        let _ = writeln!(out, "File{n}={}", song.path.display());
+        let _ = writeln!(out, "Title{n}={}", display_name(song));
+        let _ = writeln!(out, "Length{n}={}", song.duration);
+    }
+    let _ = writeln!(out, "NumberOfEntries={}", songs.len());
+    out.push_str("Version=2\n");
+    out
+}
+
+fn export_xspf(title: &str, songs: &[SongMeta]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n");
+    let _ = writeln!(out, "  <title>{}</title>", escape(title));
+    out.push_str("  <trackList>\n");
+    for song in songs {
+        out.push_str("    <track>\n");
+        let _ = writeln!(
+            out,
+            "      <location>{}</location>",
+            escape(file_url(&song.path))
+        );
+        let _ = writeln!(out, "      <title>{}</title>", escape(&song.title));
+        let _ = writeln!(
+            out,
+            "      <creator>{}</creator>",
+            escape(song.artists.join(", "))
+        );
+        if !song.album.is_empty() {
+            let _ = writeln!(out, "      <album>{}</album>", escape(&song.album));
+        }
+        let _ = writeln!(out, "      <duration>{}</duration>", song.duration * 1000);
+        out.push_str("    </track>\n");
+    }
+    out.push_str("  </trackList>\n</playlist>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn song(n: u128, path: &str, title: &str, artists: &[&str]) -> SongMeta {
+        SongMeta {
+            id: Uuid::from_u128(n),
+            title: title.to_string(),
+            artists: artists.iter().map(|a| a.to_string()).collect(),
+            album: "Album".to_string(),
+            album_artist: String::new(),
+            genre: String::new(),
+            year: None,
+            disc: None,
+            track: None,
+            duration: 200,
+            path: PathBuf::from(path),
+            added: 0,
+        }
+    }
+
+    fn library() -> Vec<SongMeta> {
+        vec![
+            song(
+                1,
+                "/music/Björk/Post/01 Army of Me.flac",
+                "Army of Me",
+                &["Björk"],
+            ),
+            song(
+                2,
+                "/music/Simon & Garfunkel/Bookends/Mrs. Robinson.mp3",
+                "Mrs. Robinson",
+                &["Simon & Garfunkel"],
+            ),
+            song(3, "/music/Various/100% <Hits>/a,b.ogg", "A, B", &["X", "Y"]),
+        ]
+    }
+
+    fn index(songs: &[SongMeta]) -> SongIndex {
+        songs.iter().map(|s| (s.id, s.clone())).collect()
+    }
+
+    #[test]
+    fn round_trips() {
+        let songs = library();
+        let index = index(&songs);
+        for format in [
+            PlaylistFormat::M3u,
+            PlaylistFormat::Pls,
+            PlaylistFormat::Xspf,
+        ] {
+            let exported = export(format, "mix & match", &songs);
+            let entries = parse(format, &exported).unwrap();
+            let (found, unmatched) = resolve(&entries, &index);
+            assert_eq!(found, songs, "{format:?}");
+            assert!(unmatched.is_empty(), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn moved_libraries_and_tags() {
+        let songs = library();
+        let index = index(&songs);
+        let m3u = "\
+#EXTM3U
+#EXTINF:200,Bjork - ARMY  OF  ME
+D:\\Old Music\\Elsewhere\\army.flac
+C:\\Music\\Simon & Garfunkel\\Bookends\\Mrs. Robinson.mp3
+#EXTINF:200,Y feat. Z - a, b
+gone.mp3
+#EXTINF:200,Garfunkel - Mrs. Robinson
+gone.mp3
+";
+        let entries = parse(PlaylistFormat::M3u, m3u).unwrap();
+        let (found, unmatched) = resolve(&entries, &index);
+        // by tags with accents folded, by trailing path, by one credited artist
+        assert_eq!(
+            found,
+            vec![songs[0].clone(), songs[1].clone(), songs[2].clone()]
+        );
+        // artists match by whole name only
+        assert_eq!(unmatched, vec!["Garfunkel - Mrs. Robinson"]);
+    }
+
+    #[test]
+    fn malformed_input() {
+        // comments, blank lines and an EXTINF without a title are skipped over
+        let entries = parse(
+            PlaylistFormat::M3u,
+            "\u{feff}#EXTM3U\n\n#EXTINF:12\n# comment\n  song.mp3  \n",
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].location.as_deref(), Some("song.mp3"));
+        assert!(entries[0].title.is_none());
+
+        // titles without a file, unknown keys and junk lines are dropped
+        let entries = parse(
+            PlaylistFormat::Pls,
+            "[playlist]\nTitle1=Lost\nFile2=b.mp3\nFilex=c.mp3\njunk\nNumberOfEntries=9\n",
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].location.as_deref(), Some("b.mp3"));
+
+        assert!(parse(PlaylistFormat::Xspf, "<playlist><trackList><track>").is_ok());
+        assert!(parse(PlaylistFormat::Xspf, "<playlist></trackList>").is_err());
+        assert!(parse(PlaylistFormat::Xspf, "").unwrap().is_empty());
+    }
+}
@@ -1,28 +1,143 @@
-use crate::{helpers::*, types::*};
+use crate::{
+    browse, counters,
+    helpers::*,
+    history::{Histories, History},
+    search::Field,
+    sessions, smart,
+    streams::{self, CancelToken, RunningStream},
+    sync,
+    types::*,
+};
 use musicman_protocols::*;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
 use symphonia::{
     core::{
-        audio::SampleBuffer, codecs::DecoderOptions, formats::FormatOptions, meta::MetadataOptions,
+        audio::SampleBuffer,
+        codecs::{Decoder, DecoderOptions},
+        formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+        meta::MetadataOptions,
+        units::Time,
     },
     default::get_probe,
 };
-use tokio::sync::mpsc;
+use tokio::sync::Mutex;
 use tracing::info;
-use uuid::Uuid;
 
+/// Spawns a task streaming the track of `play` to the client and returns it
+/// for the connection to keep track of. A prefetch stream runs alongside the
+/// current one so the client can queue the next track before the current one
+/// ends.
+pub async fn start_stream(
+    play: PlayEvent,
+    start: Start,
+    prefetch: bool,
+    state: &State,
+    index: &SongIndex,
+    ctx: &Arc<Context>,
+    write: &WriteSocket,
+) -> anyhow::Result<Option<RunningStream>> {
+    match get_track_file(&play.track_id, index).await {
+        Ok(file) => {
+            let id = streams::next_id();
+            let (cancel, token) = streams::cancel_pair();
+            let write_copy = write.clone();
+            let lead_secs = ctx.config().stream.lead_secs + state.held_secs;
+            let queue_end = state.streams.queue_end();
+            let ctx = ctx.clone();
+            let task = tokio::spawn(async move {
+                tracing::info!("Started stream {id}.");
+                let opts = StreamOptions {
+                    id,
+                    start,
+                    prefetch,
+                    begins_at: None,
+                    log: true,
+                    lead_secs,
+                    queue_end: Some(queue_end),
+                };
+                let output = Output::Client(write_copy);
+                if let Err(e) = stream_file(file, play, opts, &ctx, &output, &token).await {
+                    tracing::error!("Streaming file failed. {e}")
+                }
+            });
+            Ok(Some(RunningStream { id, cancel, task }))
+        }
+        Err(e) => {
+            let res = Response::Error {
+                message: "Track not found".to_string(),
+            };
+            tracing::warn!("{e}");
+            send_to_client(write, &res).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Where in a track a stream starts.
+#[derive(Clone, Copy)]
+pub enum Start {
+    /// at the chunk this many seconds in
+    Secs(u32),
+    /// at the chunk with this index
+    Chunk(u32),
+}
+
+/// Where a stream starts and how its header announces it.
+pub struct StreamOptions {
+    pub id: StreamId,
+    pub start: Start,
+    pub prefetch: bool,
+    /// Unix milliseconds at which the beginning of the track plays, for
+    /// streams shared by a synced session.
+    pub begins_at: Option<u64>,
+    /// Whether the play goes into the history. Off for streams repeating a
+    /// play that is already logged.
+    pub log: bool,
+    /// How far ahead of playback the stream may run, in seconds of audio.
+    pub lead_secs: u64,
+    /// Unix milliseconds at which the audio a client has queued runs out. A
+    /// prefetch is paced from there rather than from now, and every stream
+    /// moves it on to its own end once it has all been sent.
+    pub queue_end: Option<Arc<AtomicU64>>,
+}
+
+/// Chunks between timing markers on synced streams, about three seconds
+/// of CD audio.
+const TIMING_EVERY: u32 = 32;
+
+/// Streams a decoded file to `output` in chunks of `CHUNK_FRAMES`, from
+/// `opts.start` on, logging `play` to the history once enough of it has been sent. Chunks go
+/// out as they come up for playback, no more than the configured lead ahead.
+/// Returns the length of the track in milliseconds, or `None` if it was
+/// cancelled.
 pub async fn stream_file(
     file: tokio::fs::File,
-    track_id: Uuid,
-    stream: &WriteSocket,
-    mut cancel_rx: mpsc::Receiver<()>,
-) -> anyhow::Result<()> {
+    mut play: PlayEvent,
+    opts: StreamOptions,
+    ctx: &Context,
+    output: &Output,
+    cancel: &CancelToken,
+) -> anyhow::Result<Option<u64>> {
+    let _active = counters::ActiveStream::start();
+    let track_id = play.track_id;
     let std_file = file.into_std().await;
     let mss = symphonia::core::io::MediaSourceStream::new(Box::new(std_file), Default::default());
     info!("Probing file types");
+    // Gapless mode trims encoder delay and padding when the container provides them.
+    let format_opts = FormatOptions {
+        enable_gapless: true,
+        ..Default::default()
+    };
     let probed = get_probe().format(
         &Default::default(),
         mss,
-        &FormatOptions::default(),
+        &format_opts,
         &MetadataOptions::default(),
     )?;
     let mut format = probed.format;
@@ -33,6 +148,7 @@ pub async fn stream_file(
         .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
         .ok_or_else(|| anyhow::anyhow!("No supported audio tracks"))?;
 
+    let track_no = track.id;
     let dec_opts = DecoderOptions { verify: true };
     let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &dec_opts)?;
     info!("Finding sample rate and channels");
@@ -43,91 +159,624 @@ pub async fn stream_file(
         .channels
         .map(|c| c.count() as u16)
         .unwrap_or(1);
+    let time_base = track.codec_params.time_base;
+
+    // samples to send before it counts as played
+    let threshold = ctx.config().history;
+    let by_time = threshold.threshold_secs * sample_rate as u64;
+    let by_percent = track
+        .codec_params
+        .n_frames
+        .map(|n| n * threshold.threshold_percent as u64 / 100);
+    let play_at = by_percent.map_or(by_time, |p| p.min(by_time)) * channels as u64;
+
+    let chunk_len = CHUNK_FRAMES as usize * channels as usize;
+    let mut index = match opts.start {
+        Start::Secs(secs) => (secs as u64 * sample_rate as u64 / CHUNK_FRAMES) as u32,
+        Start::Chunk(index) => index,
+    };
+    let start_frame = index as u64 * CHUNK_FRAMES;
+    // samples decoded past the seek point that come before the first chunk
+    let mut skip = 0;
+    if start_frame > 0 {
+        let time = Time::new(
+            start_frame / sample_rate as u64,
+            (start_frame % sample_rate as u64) as f64 / sample_rate as f64,
+        );
+        let seeked = format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time,
+                track_id: Some(track_no),
+            },
+        )?;
+        let frames = |ts| match time_base {
+            Some(base) => {
+                let time = base.calc_time(ts);
+                time.seconds * sample_rate as u64 + (time.frac * sample_rate as f64).round() as u64
+            }
+            None => ts,
+        };
+        skip = frames(seeked.required_ts).saturating_sub(frames(seeked.actual_ts)) as usize
+            * channels as usize;
+        decoder.reset();
+    }
+    let mut sent = start_frame * channels as u64;
+    // A stream starting past the threshold resumes or continues a play
+    // that an earlier stream has logged already.
+    let mut logged = !opts.log || (sent > 0 && sent >= play_at);
 
     info!("Preparing Header");
+    // where the track has got to after `sent` samples, in milliseconds
+    let millis = |sent: u64| sent / channels as u64 * 1000 / sample_rate as u64;
     let header = Response::SongHeader {
-        track_id: track_id.clone(),
+        stream_id: opts.id,
+        track_id,
         channels,
         sample_rate,
+        prefetch: opts.prefetch,
+        start_at: opts.begins_at.map(|at| at + millis(sent)),
+        played_after: millis(play_at).div_ceil(1000) as u32,
     };
 
-    send_to_client(stream, &header).await?;
+    send_to_output(output, &header).await?;
     tracing::info!("Sent Header.");
 
-    let mut index: u32 = 0;
+    // Unsynced streams start playing about as soon as they arrive, or
+    // after the current track for a prefetch.
+    let begins_at = opts.begins_at.unwrap_or_else(|| {
+        let now = unix_now_ms();
+        let starts = match &opts.queue_end {
+            Some(end) if opts.prefetch => end.load(Ordering::Relaxed).max(now),
+            _ => now,
+        };
+        starts.saturating_sub(millis(sent))
+    });
+    let lead_ms = opts.lead_secs * 1000;
+
+    // Packets decode to any number of samples, so they are collected here
+    // until a whole chunk is ready.
+    let mut pending: Vec<i16> = Vec::with_capacity(chunk_len * 2);
+    let mut ended = false;
+
+    loop {
+        while !ended && pending.len() < chunk_len {
+            match next_samples(&mut format, &mut decoder)? {
+                Some(samples) => {
+                    let skipped = skip.min(samples.len());
+                    skip -= skipped;
+                    pending.extend_from_slice(&samples[skipped..]);
+                }
+                None => ended = true,
+            }
+        }
+        if pending.is_empty() {
+            break;
+        }
+        let data: Vec<i16> = pending.drain(..chunk_len.min(pending.len())).collect();
+        let len = data.len() as u64;
+        let res = Response::SongChunk {
+            stream_id: opts.id,
+            track_id,
+            data,
+            index,
+        };
+
+        // A write is never cut short, since that would leave half a
+        // message on the socket, so cancelling takes effect between chunks.
+        if cancel.is_cancelled() {
+            info!("Stopping stream {}", opts.id);
+            return Ok(None);
+        }
+        let send_at = (begins_at + millis(sent)).saturating_sub(lead_ms);
+        let wait = send_at.saturating_sub(unix_now_ms());
+        if wait > 0 {
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_millis(wait)) => {}
+                _ = cancel.cancelled() => {
+                    info!("Stopping stream {}", opts.id);
+                    return Ok(None);
+                }
+            }
+        }
+        if let Some(begins_at) = opts.begins_at
+            && index.is_multiple_of(TIMING_EVERY)
+        {
+            let timing = Response::Timing {
+                stream_id: opts.id,
+                track_id,
+                index,
+                at: begins_at + millis(sent),
+            };
+            send_to_output(output, &timing).await?;
+        }
+        if let Err(e) = send_to_output(output, &res).await {
+            tracing::error!("Streaming failed.");
+            return Err(e);
+        }
+        index += 1;
+        counters::count_streamed(len as usize * size_of::<i16>());
+
+        sent += len;
+        if !logged && sent >= play_at {
+            logged = true;
+            log_play(ctx, &mut play).await;
+        }
+    }
 
+    if let Some(end) = &opts.queue_end {
+        end.store(begins_at + millis(sent), Ordering::Relaxed);
+    }
+    let res = Response::EndOfStream {
+        stream_id: opts.id,
+        track_id,
+    };
+    send_to_output(output, &res).await?;
+    if !logged {
+        log_play(ctx, &mut play).await;
+    }
+
+    Ok(Some(millis(sent)))
+}
+
+/// Decodes the next packet of the track into interleaved samples, or
+/// returns `None` at the end of the file.
+fn next_samples(
+    format: &mut Box<dyn FormatReader>,
+    decoder: &mut Box<dyn Decoder>,
+) -> anyhow::Result<Option<Vec<i16>>> {
     loop {
         let packet = match format.next_packet() {
             Ok(pkt) => pkt,
-            Err(symphonia::core::errors::Error::IoError(_)) => break, // EOF
+            Err(symphonia::core::errors::Error::IoError(_)) => return Ok(None), // EOF
             Err(e) => return Err(e.into()),
         };
 
         let decoded = match decoder.decode(&packet) {
             Ok(decoded) => decoded,
-            Err(symphonia::core::errors::Error::DecodeError(_)) => continue,
+            Err(symphonia::core::errors::Error::DecodeError(_)) => {
+                counters::count_decode_error();
+                continue;
+            }
             Err(e) => return Err(e.into()),
         };
 
         let duration = decoded.capacity() as u64;
         let mut sample_buf = SampleBuffer::<i16>::new(duration, *decoded.spec());
         sample_buf.copy_interleaved_ref(decoded);
+        return Ok(Some(sample_buf.samples().to_vec()));
+    }
+}
 
-        let samples = sample_buf.samples();
+async fn log_play(ctx: &Context, play: &mut PlayEvent) {
+    play.time = unix_now();
+    if let Err(e) = ctx.history.lock().await.record(play.clone()).await {
+        tracing::warn!("Could not save play history. {e}")
+    }
+}
+pub async fn handle_search(
+    s: SearchType,
+    library: &Library,
+    history: &Mutex<Histories>,
+    user: Option<&str>,
+) -> Response {
+    let (query, fields) = match &s {
+        SearchType::ByTitle(query) => (query, &[Field::Title][..]),
+        SearchType::ByArtist(query) => (query, &[Field::Artist][..]),
+        SearchType::Query(expr) => {
+            return match expr.parse::<Query>() {
+                Ok(query) => {
+                    let history = history.lock().await;
+                    let stats = history.of(user).stats();
+                    Response::SearchResults(search_query(&query, library, stats))
+                }
+                Err(e) => Response::Error {
+                    message: format!("Invalid search: {e}"),
+                },
+            };
+        }
+    };
+    Response::SearchResults(library.search().search(query, fields, library.songs()))
+}
 
-        for chunk in samples.chunks(8192) {
-            let data: Vec<i16> = chunk.to_vec();
-            let res = Response::SongChunk {
-                track_id: track_id.clone(),
-                data,
-                index,
+/// Free text goes through the search index for ranking and typo tolerance,
+/// then the remaining filters narrow the results down. Without free text
+/// it's the same as evaluating a smart playlist.
+fn search_query(query: &Query, library: &Library, stats: &StatsIndex) -> Vec<SongMeta> {
+    let now = unix_now();
+    let filters = Query {
+        filters: query
+            .filters
+            .iter()
+            .filter(|f| !matches!(f, Filter::Words(_)))
+            .cloned()
+            .collect(),
+    };
+
+    let words = query.words();
+    if words.is_empty() {
+        return smart::evaluate(&filters, library.songs(), stats, now);
+    }
+    library
+        .search()
+        .search(
+            &words,
+            &[Field::Title, Field::Artist, Field::Album],
+            library.songs(),
+        )
+        .into_iter()
+        .filter(|song| smart::matches(&filters, song, stats.get(&song.id), now))
+        .collect()
+}
+
+pub fn handle_browse(req: BrowseRequest, index: &SongIndex) -> Response {
+    let res = match req {
+        BrowseRequest::Artists => BrowseResponse::Artists(browse::artists(index)),
+        BrowseRequest::Albums { artist } => BrowseResponse::Albums {
+            albums: browse::albums(index, &artist),
+            artist,
+        },
+        BrowseRequest::Album { name } => {
+            return Response::SearchResults(browse::album(index, &name));
+        }
+        BrowseRequest::Genres => BrowseResponse::Genres(browse::genres(index)),
+        BrowseRequest::Years => BrowseResponse::Years(browse::years(index)),
+    };
+    Response::Browse(res)
+}
+
+pub fn handle_history(req: HistoryRequest, index: &SongIndex, history: &History) -> Response {
+    // windows are relative to now
+    let since = |within: Option<u64>| within.map_or(0, |w| unix_now().saturating_sub(w));
+    let res = match req {
+        HistoryRequest::Recent { limit } => HistoryResponse::Recent(history.recent(limit, index)),
+        HistoryRequest::TopTracks { within, limit } => {
+            HistoryResponse::TopTracks(history.top_tracks(since(within), limit, index))
+        }
+        HistoryRequest::TopArtists { within, limit } => {
+            HistoryResponse::TopArtists(history.top_artists(since(within), limit, index))
+        }
+        HistoryRequest::PlayCount { track_id } => {
+            let Some(song) = index.get(&track_id) else {
+                return Response::Error {
+                    message: "Track not found".to_string(),
+                };
             };
+            let stats = history.stats().get(&track_id).cloned().unwrap_or_default();
+            HistoryResponse::PlayCount {
+                song: song.clone(),
+                plays: stats.plays,
+                last_played: stats.last_played,
+            }
+        }
+    };
+    Response::History(res)
+}
 
-            if let Err(e) = cancel_rx.try_recv() {
-                if e == mpsc::error::TryRecvError::Disconnected {
-                    info!("Stopping stream");
-                    return Ok(());
-                }
+/// Attaches, detaches or updates the session the client is attached to,
+/// which is tracked in `attached` by its key.
+pub async fn handle_session(
+    req: SessionRequest,
+    attached: &mut Option<String>,
+    user: Option<&str>,
+    ctx: &Arc<Context>,
+    index: &SongIndex,
+    write: &WriteSocket,
+) -> Option<Response> {
+    let mut all = ctx.sessions.lock().await;
+    let res = match req {
+        SessionRequest::Attach { name } => {
+            let name = name.trim().to_lowercase();
+            if name.is_empty() {
+                return Some(Response::Error {
+                    message: "Session names can't be empty".to_string(),
+                });
             }
-            if let Err(e) = send_to_client(stream, &res).await {
-                tracing::error!("Streaming failed.");
-                return Err(e);
+            let Some(key) = sessions::key(user, &name) else {
+                return Some(Response::Error {
+                    message: "Session names can't contain '/'".to_string(),
+                });
+            };
+            if let Some(old) = attached.take() {
+                sessions::detach(&mut all, &old, write).await;
+            }
+            let state = sessions::attach(&mut all, &key, index, write).await;
+            if let Some(session) = all.get_mut(&key)
+                && session.sync
+            {
+                sync::catch_up(ctx, session, write);
+            }
+            *attached = Some(key);
+            SessionResponse::Attached(state)
+        }
+        SessionRequest::Detach => {
+            let Some(key) = attached.take() else {
+                return Some(Response::Error {
+                    message: "Not attached to a session".to_string(),
+                });
+            };
+            sessions::detach(&mut all, &key, write).await;
+            let ctx = ctx.clone();
+            tokio::spawn(async move { sessions::flush(&ctx.sessions).await });
+            SessionResponse::Detached {
+                name: sessions::display_name(&key).to_string(),
+            }
+        }
+        SessionRequest::Update(update) => {
+            // updates are frequent, so they aren't acknowledged
+            if let Some(key) = attached.as_deref()
+                && let Some(session) = all.get_mut(key)
+            {
+                session.update(update);
             }
-            index += 1;
+            return None;
         }
+        SessionRequest::Sync(on) => {
+            let Some((key, session)) = attached
+                .as_ref()
+                .and_then(|key| all.get_mut(key).map(|session| (key, session)))
+            else {
+                return Some(Response::Error {
+                    message: "Not attached to a session".to_string(),
+                });
+            };
+            session.sync = on;
+            if !on {
+                sync::stop(session);
+            }
+            // everyone in the session hears about it, this client included
+            let res = Response::Session(SessionResponse::Synced {
+                name: sessions::display_name(key).to_string(),
+                on,
+            });
+            let output = Output::Session(session.listeners.clone());
+            drop(all);
+            if let Err(e) = send_to_output(&output, &res).await {
+                tracing::warn!("{e}");
+            }
+            return None;
+        }
+        SessionRequest::List => SessionResponse::Sessions(sessions::list(&all, user)),
+    };
+    Some(Response::Session(res))
+}
+
+/// Registers this connection as `name` among its user's clients, keyed like
+/// sessions. A name held by another live connection stays with it, unless
+/// that connection is from the same host: a client reconnecting before the
+/// server noticed its old connection drop takes its name back.
+pub async fn handle_register(
+    name: String,
+    registered: &mut Option<String>,
+    user: Option<&str>,
+    ctx: &Context,
+    addr: &str,
+    write: &WriteSocket,
+) -> Option<Response> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return Some(Response::Error {
+            message: "Client names can't be empty".to_string(),
+        });
+    }
+    let Some(key) = sessions::key(user, &name) else {
+        return Some(Response::Error {
+            message: "Client names can't contain '/' without a login".to_string(),
+        });
+    };
+
+    let mut clients = ctx.clients.lock().await;
+    if let Some(holder) = clients.get(&key)
+        && !Arc::ptr_eq(&holder.write, write)
+        && peer_host(&holder.addr) != peer_host(addr)
+        && ctx.connections.lock().await.contains_key(&holder.addr)
+    {
+        return Some(Response::Error {
+            message: format!("Another client is already called {name}"),
+        });
     }
+    if let Some(old) = registered.take()
+        && clients
+            .get(&old)
+            .is_some_and(|c| Arc::ptr_eq(&c.write, write))
+    {
+        clients.remove(&old);
+    }
+    clients.insert(
+        key.clone(),
+        RemoteClient {
+            addr: addr.to_string(),
+            write: write.clone(),
+        },
+    );
+    *registered = Some(key);
+    None
+}
 
-    let res = Response::EndOfStream {
-        track_id: track_id.clone(),
+/// The host part of a client address, `unix` for Unix socket clients.
+fn peer_host(addr: &str) -> &str {
+    addr.rsplit_once(':').map_or(addr, |(host, _)| host)
+}
+
+/// Drops the registration of a closed connection, unless a newer one has
+/// taken the name since.
+pub async fn unregister(ctx: &Context, name: &str, write: &WriteSocket) {
+    let mut clients = ctx.clients.lock().await;
+    if clients
+        .get(name)
+        .is_some_and(|c| Arc::ptr_eq(&c.write, write))
+    {
+        clients.remove(name);
+    }
+}
+
+/// Passes `command` on to `user`'s client registered as `target`. Nothing
+/// goes back to the sender unless that fails.
+pub async fn handle_control(
+    target: String,
+    command: Control,
+    from: String,
+    user: Option<&str>,
+    ctx: &Context,
+) -> Option<Response> {
+    let target = target.trim().to_lowercase();
+    let write = match sessions::key(user, &target) {
+        Some(key) => ctx.clients.lock().await.get(&key).map(|c| c.write.clone()),
+        None => None,
     };
-    send_to_client(stream, &res).await?;
+    let Some(write) = write else {
+        return Some(Response::Error {
+            message: format!("No client named {target}"),
+        });
+    };
+
+    let res = Response::Control { from, command };
+    match send_to_client(&write, &res).await {
+        Ok(()) => None,
+        Err(e) => Some(Response::Error {
+            message: format!("Could not reach {target}: {e}"),
+        }),
+    }
+}
 
-    Ok(())
+/// The clients `user` has registered.
+pub async fn list_clients(ctx: &Context, user: Option<&str>) -> Response {
+    let user = user.map(user_key);
+    let mut clients: Vec<ClientInfo> = ctx
+        .clients
+        .lock()
+        .await
+        .iter()
+        .filter(|(key, _)| sessions::owner(key) == user.as_deref())
+        .map(|(key, c)| ClientInfo {
+            name: sessions::display_name(key).to_string(),
+            addr: c.addr.clone(),
+        })
+        .collect();
+    clients.sort_by(|a, b| a.name.cmp(&b.name));
+    Response::Clients(clients)
 }
-pub async fn handle_search(s: SearchType, index: &SongIndex) -> Vec<SongMeta> {
-    let mut results = Vec::new();
 
-    match s {
-        SearchType::ByTitle(query) => {
-            let q = query.to_lowercase();
-            for (_id, meta) in index {
-                if meta.title.to_lowercase().contains(&q) {
-                    results.push(meta.clone());
-                }
+/// The playlist a request changes, if any.
+fn changed_playlist(req: &PlaylistRequest) -> Option<&str> {
+    match req {
+        PlaylistRequest::List | PlaylistRequest::Get { .. } | PlaylistRequest::Export { .. } => {
+            None
+        }
+        PlaylistRequest::Create { name, .. }
+        | PlaylistRequest::CreateSmart { name, .. }
+        | PlaylistRequest::Rename { name, .. }
+        | PlaylistRequest::Delete { name }
+        | PlaylistRequest::AddSongs { name, .. }
+        | PlaylistRequest::RemoveSongs { name, .. }
+        | PlaylistRequest::Move { name, .. }
+        | PlaylistRequest::Import { name, .. }
+        | PlaylistRequest::Share { name, .. } => Some(name),
+    }
+}
+
+/// Handles a playlist request from `user`, whose own playlists are the
+/// only ones it can change.
+pub async fn handle_playlist(
+    req: PlaylistRequest,
+    user: Option<&str>,
+    index: &SongIndex,
+    history: &Mutex<Histories>,
+) -> Response {
+    if changed_playlist(&req).is_some_and(|name| name.contains('/')) {
+        return Response::Error {
+            message: "Playlists of other users are read-only".to_string(),
+        };
+    }
+    let result = match req {
+        PlaylistRequest::List => get_all_playlists(user, index, history)
+            .await
+            .map(PlaylistResponse::Playlists),
+        PlaylistRequest::Get { name } => get_playlist_songs(name, user, index, history).await.map(
+            |(songs, positions, missing)| PlaylistResponse::Songs {
+                songs,
+                positions,
+                missing,
+            },
+        ),
+        PlaylistRequest::Create { name, songs } => create_playlist(name, user, songs, index)
+            .await
+            .map(PlaylistResponse::Saved),
+        PlaylistRequest::CreateSmart { name, query } => {
+            create_smart_playlist(name, user, query, index, history)
+                .await
+                .map(PlaylistResponse::Saved)
+        }
+        PlaylistRequest::Rename { name, new_name } => {
+            rename_playlist(name, new_name, user, index, history)
+                .await
+                .map(PlaylistResponse::Saved)
+        }
+        PlaylistRequest::Delete { name } => delete_playlist(name.clone(), user)
+            .await
+            .map(|_| PlaylistResponse::Deleted { name }),
+        PlaylistRequest::Share { name, shared } => {
+            share_playlist(name, user, shared, index, history)
+                .await
+                .map(PlaylistResponse::Saved)
+        }
+        PlaylistRequest::AddSongs { name, songs } => update_playlist(name, user, |list| {
+            list.extend(entries_for(songs, index)?);
+            Ok(())
+        })
+        .await
+        .map(PlaylistResponse::Saved),
+        PlaylistRequest::RemoveSongs { name, mut indices } => update_playlist(name, user, |list| {
+            indices.sort_unstable();
+            indices.dedup();
+            if indices.last().is_some_and(|i| *i >= list.len()) {
+                return Err(anyhow::anyhow!("Playlist only has {} entries", list.len()));
+            }
+            for i in indices.into_iter().rev() {
+                list.remove(i);
             }
+            Ok(())
+        })
+        .await
+        .map(PlaylistResponse::Saved),
+        PlaylistRequest::Move { name, from, to } => update_playlist(name, user, |list| {
+            if from >= list.len() || to >= list.len() {
+                return Err(anyhow::anyhow!("Playlist only has {} entries", list.len()));
+            }
+            let song = list.remove(from);
+            list.insert(to, song);
+            Ok(())
+        })
+        .await
+        .map(PlaylistResponse::Saved),
+        PlaylistRequest::Import {
+            name,
+            format,
+            contents,
+        } => import_playlist(name, user, format, &contents, index)
+            .await
+            .map(|(playlist, unmatched)| PlaylistResponse::Imported {
+                playlist,
+                unmatched,
+            }),
+        PlaylistRequest::Export { name, format } => {
+            export_playlist(name.clone(), user, format, index, history)
+                .await
+                .map(|contents| PlaylistResponse::Exported {
+                    name,
+                    format,
+                    contents,
+                })
         }
-        SearchType::ByArtist(query) => {
-            let q = query.to_lowercase();
-            for (_id, meta) in index {
-                for artist in meta.artists.clone() {
-                    if artist.to_lowercase().contains(&q) {
-                        results.push(meta.clone());
-                        break;
-                    }
-                }
+    };
+
+    match result {
+        Ok(plres) => Response::Playlist(plres),
+        Err(e) => {
+            tracing::warn!("Playlist request failed: {e}");
+            Response::Error {
+                message: e.to_string(),
             }
         }
     }
-
-    results
 }
@@ -1,7 +1,12 @@
-use crate::types::*;
+use crate::{counters, formats, history::Histories, smart, types::*};
 use anyhow::Result;
 use musicman_protocols::*;
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 use symphonia::{
     core::{
         formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, units::TimeStamp,
@@ -11,20 +16,105 @@ use symphonia::{
 use tokio::{
     fs::{self, File, OpenOptions},
     io::AsyncWriteExt,
+    sync::{
+        Mutex,
+        mpsc::{self, error::TrySendError},
+    },
 };
 use uuid::Uuid;
 use walkdir::WalkDir;
 
 pub async fn send_to_client(socket: &WriteSocket, response: &Response) -> anyhow::Result<()> {
+    write_frame(socket, &encode(response)?).await
+}
+
+/// A response as it goes over the wire, its length first.
+fn encode(response: &Response) -> anyhow::Result<Vec<u8>> {
     let encoded: Vec<u8> = bincode::serialize(response)?;
-    let len = (encoded.len() as u32).to_be_bytes();
+    let mut frame = (encoded.len() as u32).to_be_bytes().to_vec();
+    frame.extend(encoded);
+    Ok(frame)
+}
+
+async fn write_frame(socket: &WriteSocket, frame: &[u8]) -> anyhow::Result<()> {
     let mut socket_locked = socket.lock().await;
-    socket_locked.write_all(&len).await?;
-    socket_locked.write_all(&encoded).await?;
+    socket_locked.write_all(frame).await?;
+    // TLS may hold on to the tail of a record until flushed
+    socket_locked.flush().await?;
+    counters::count_sent(frame.len());
     Ok(())
 }
 
-pub async fn generate_index(music_dir: &PathBuf) -> anyhow::Result<()> {
+/// Messages a session listener can have waiting before it counts as fallen
+/// behind. In stream chunks that's over forty seconds of CD audio, room for
+/// the lead and a crossfade's hold-back.
+const LISTENER_QUEUE: usize = 512;
+
+impl Listener {
+    /// Starts writing whatever gets queued for `write` in the background.
+    pub fn new(write: WriteSocket) -> Self {
+        let (queue, mut queued) = mpsc::channel::<Arc<Vec<u8>>>(LISTENER_QUEUE);
+        let socket = write.clone();
+        tokio::spawn(async move {
+            while let Some(frame) = queued.recv().await {
+                if write_frame(&socket, &frame).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Listener { write, queue }
+    }
+}
+
+/// Sends `response` to every client behind `output`. Clients of a session
+/// only have it queued, and those whose queue is full or closed are dropped
+/// from the session's listeners; it's only an error once nobody is left.
+pub async fn send_to_output(output: &Output, response: &Response) -> anyhow::Result<()> {
+    match output {
+        Output::Client(socket) => send_to_client(socket, response).await,
+        Output::Session(listeners) => {
+            let frame = Arc::new(encode(response)?);
+            let mut listeners = listeners.lock().await;
+            listeners.retain(|listener| match listener.queue.try_send(frame.clone()) {
+                Ok(()) => true,
+                // Waiting for it would put the whole session out of step.
+                Err(TrySendError::Full(_)) => {
+                    tracing::warn!("Dropped a client that fell behind its session.");
+                    let write = listener.write.clone();
+                    tokio::spawn(async move {
+                        let res = Response::Error {
+                            message: "Fell behind the session, attach to it again to catch up"
+                                .to_string(),
+                        };
+                        let _ = send_to_client(&write, &res).await;
+                    });
+                    false
+                }
+                Err(TrySendError::Closed(_)) => false,
+            });
+            match listeners.is_empty() {
+                false => Ok(()),
+                true => Err(anyhow::anyhow!("No clients left in the session")),
+            }
+        }
+    }
+}
+
+pub fn music_dir() -> PathBuf {
+    dirs::home_dir().unwrap().join("Music")
+}
+
+/// Indexes every supported file under `music_dir`. An incremental scan keeps
+/// the entries of files unchanged since the last index was saved instead of
+/// reading them again. `progress` is told how many files are done out of
+/// how many as it goes. It reads every file, so run it off the runtime
+/// threads.
+pub fn generate_index(
+    music_dir: &PathBuf,
+    incremental: bool,
+    mut progress: impl FnMut(usize, usize),
+) -> anyhow::Result<SongIndex> {
+    let started = Instant::now();
     // collect supported audio files
     let mut songs: Vec<PathBuf> = Vec::new();
     for entry in WalkDir::new(music_dir)
@@ -43,9 +133,27 @@ pub async fn generate_index(music_dir: &PathBuf) -> anyhow::Result<()> {
 
     tracing::info!("Found {} songs.", songs.len());
 
+    // keep when each track was first seen across re-indexes
+    let previous = load_index().unwrap_or_default();
+    let last_scan = match incremental {
+        true => index_modified(),
+        false => None,
+    };
+    let now = unix_now();
     let mut index: SongIndex = HashMap::new();
 
-    for path in songs {
+    let total = songs.len();
+    for (done, path) in songs.into_iter().enumerate() {
+        progress(done, total);
+        let id = uuid::Uuid::new_v5(&Uuid::NAMESPACE_URL, path.display().to_string().as_bytes());
+        if let Some(last_scan) = last_scan
+            && let Some(song) = previous.get(&id)
+            && modified(&path).is_some_and(|m| m < last_scan)
+        {
+            index.insert(id, song.clone());
+            continue;
+        }
+
         let file = match std::fs::File::open(&path) {
             Ok(f) => f,
             Err(e) => {
@@ -77,13 +185,19 @@ pub async fn generate_index(music_dir: &PathBuf) -> anyhow::Result<()> {
             .unwrap_or("Unknown")
             .to_string();
         let mut artist = "Unknown".to_string();
+        let mut album = String::new();
+        let mut album_artist = String::new();
+        let mut genre = String::new();
+        let mut year: Option<u32> = None;
+        let mut disc: Option<u32> = None;
+        let mut track_no: Option<u32> = None;
         let mut duration_secs: u32 = 0;
         let mut meta_opt = format.metadata();
 
-        if meta_opt.current().is_none() {
-            if let Some(meta) = probe.metadata.get() {
-                meta_opt = meta;
-            }
+        if meta_opt.current().is_none()
+            && let Some(meta) = probe.metadata.get()
+        {
+            meta_opt = meta;
         }
         if let Some(rev) = meta_opt.current() {
             // rev.tags() returns an iterator of tags; tag.key and tag.value are Options
@@ -93,61 +207,112 @@ pub async fn generate_index(music_dir: &PathBuf) -> anyhow::Result<()> {
                 match key.to_lowercase().as_str() {
                     "title" | "tit2" if !val.is_empty() => title = val.to_string(),
                     "artist" | "tpe1" if !val.is_empty() => artist = val.to_string(),
+                    "album" | "talb" if !val.is_empty() => album = val.to_string(),
+                    "albumartist" | "album artist" | "album_artist" | "tpe2" if !val.is_empty() => {
+                        album_artist = val.to_string()
+                    }
+                    "genre" | "tcon" if !val.is_empty() => genre = val.to_string(),
+                    "date" | "year" | "tdrc" | "tyer" if year.is_none() => {
+                        year = val.get(..4).and_then(|y| y.parse().ok())
+                    }
+                    // "3" or "3/12"
+                    "tracknumber" | "trck" | "track" => track_no = leading_number(&val),
+                    "discnumber" | "tpos" | "disc" => disc = leading_number(&val),
                     _ => {}
                 }
             }
         }
 
-        if let Some(track) = format.tracks().first() {
-            if let (Some(tb), Some(n_frames)) =
+        if let Some(track) = format.tracks().first()
+            && let (Some(tb), Some(n_frames)) =
                 (track.codec_params.time_base, track.codec_params.n_frames)
-            {
-                let ts: TimeStamp = n_frames as TimeStamp;
-                let time = tb.calc_time(ts); // has .seconds (u64) and .frac (f64)
-                let secs_f = (time.seconds as f64) + time.frac;
-                duration_secs = secs_f.max(0.0).round() as u32;
-            }
+        {
+            let ts: TimeStamp = n_frames as TimeStamp;
+            let time = tb.calc_time(ts); // has .seconds (u64) and .frac (f64)
+            let secs_f = (time.seconds as f64) + time.frac;
+            duration_secs = secs_f.max(0.0).round() as u32;
         }
 
-        let id = uuid::Uuid::new_v5(&Uuid::NAMESPACE_URL, path.display().to_string().as_bytes());
         let artists = artist
             .split('/')
             .map(|s| s.to_string())
             .collect::<Vec<String>>();
 
+        let added = previous
+            .get(&id)
+            .map(|s| s.added)
+            .filter(|added| *added > 0)
+            .or_else(|| modified(&path))
+            .unwrap_or(now);
+
         let songmeta = SongMeta {
             id,
             title,
             artists,
+            album,
+            album_artist,
+            genre,
+            year,
+            disc,
+            track: track_no,
             duration: duration_secs,
             path,
+            added,
         };
 
         index.insert(id, songmeta);
     }
 
+    progress(total, total);
     tracing::info!("Indexed {} songs.", index.len());
-    save_index(&index).await?;
-    Ok(())
+    save_index(&index)?;
+    counters::set_scan_duration(started.elapsed());
+    Ok(index)
 }
 
-pub async fn load_index() -> anyhow::Result<SongIndex> {
+/// Unix seconds at which `path` was last modified.
+fn modified(path: &Path) -> Option<u64> {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+}
+
+/// When the saved index was last written, in unix seconds.
+fn index_modified() -> Option<u64> {
+    let index_file = dirs::config_dir()?.join("musicman").join("index.json");
+    let modified = std::fs::metadata(index_file).ok()?.modified().ok()?;
+    modified
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+fn leading_number(val: &str) -> Option<u32> {
+    val.split('/').next()?.trim().parse().ok()
+}
+
+fn load_index() -> anyhow::Result<SongIndex> {
     let index_file = dirs::config_dir()
         .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?
         .join("musicman")
         .join("index.json");
-    let data = tokio::fs::read_to_string(index_file).await?;
+    let data = std::fs::read_to_string(index_file)?;
     let index: SongIndex = serde_json::from_str(&data)?;
     Ok(index)
 }
 
-pub async fn save_index(index: &SongIndex) -> anyhow::Result<()> {
+fn save_index(index: &SongIndex) -> anyhow::Result<()> {
     let index_file = dirs::config_dir()
         .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?
         .join("musicman")
         .join("index.json");
     let data = serde_json::to_string_pretty(index)?;
-    tokio::fs::write(index_file, data).await?;
+    // written aside and renamed over, so the index is never left half-written
+    let tmp_file = index_file.with_extension("json.tmp");
+    std::fs::write(&tmp_file, data)?;
+    std::fs::rename(tmp_file, index_file)?;
     Ok(())
 }
 
@@ -171,64 +336,472 @@ pub async fn get_track_meta(
     }
 }
 
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn unix_now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
 fn sanitize(name: String) -> String {
     name.chars()
         .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
         .collect()
 }
 
-pub async fn get_playlist(name: String) -> Result<PlaylistMeta> {
-    let playlists_dir = dirs::config_dir()
-        .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?
-        .join("musicman")
-        .join("playlists");
+/// How a user's name appears on disk. Logins are matched case-insensitively,
+/// so `Alice` and `alice` share one directory.
+pub fn user_key(user: &str) -> String {
+    sanitize(user.to_lowercase())
+}
 
-    let file_path = playlists_dir.join(format!("{}.json", sanitize(name)));
-    let data = fs::read_to_string(file_path).await?;
-    let playlist: PlaylistMeta = serde_json::from_str(&data)?;
-    Ok(playlist)
+fn same_user(a: Option<&str>, b: Option<&str>) -> bool {
+    a.map(user_key) == b.map(user_key)
 }
 
-pub async fn get_all_playlists() -> Result<Vec<Playlist>> {
-    let playlists_dir = dirs::config_dir()
+/// Where a user's playlists and history are kept. Clients that haven't
+/// logged in use the musicman config directory itself.
+pub fn data_dir(user: Option<&str>) -> Result<PathBuf> {
+    let dir = dirs::config_dir()
         .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?
-        .join("musicman")
-        .join("playlists");
+        .join("musicman");
+    Ok(match user {
+        Some(user) => dir.join("users").join(user_key(user)),
+        None => dir,
+    })
+}
+
+/// The users that have a data directory, other than the anonymous one.
+async fn known_users() -> Result<Vec<String>> {
+    let mut users = Vec::new();
+    if let Ok(mut dir) = fs::read_dir(data_dir(None)?.join("users")).await {
+        while let Some(entry) = dir.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                users.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+    }
+    Ok(users)
+}
+
+fn playlists_dir(user: Option<&str>) -> Result<PathBuf> {
+    Ok(data_dir(user)?.join("playlists"))
+}
+
+fn playlist_path(user: Option<&str>, name: String) -> Result<PathBuf> {
+    let name = sanitize(name);
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("Invalid playlist name"));
+    }
+    Ok(playlists_dir(user)?.join(format!("{}.json", name)))
+}
+
+fn smart_playlist_path(user: Option<&str>, name: String) -> Result<PathBuf> {
+    Ok(playlist_path(user, name)?.with_extension("smart"))
+}
+
+/// The smart playlist called `name`, if there is one.
+async fn get_smart_playlist(user: Option<&str>, name: String) -> Result<Option<SmartPlaylist>> {
+    match fs::read_to_string(smart_playlist_path(user, name)?).await {
+        Ok(data) => Ok(Some(serde_json::from_str(&data)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
 
-    let mut dir = fs::read_dir(playlists_dir).await?;
+async fn playlist_exists(user: Option<&str>, name: String) -> Result<bool> {
+    Ok(fs::try_exists(playlist_path(user, name.clone())?).await?
+        || fs::try_exists(smart_playlist_path(user, name)?).await?)
+}
+
+/// Whose playlist `name` refers to. Other users' playlists are named
+/// `owner/name`; anything else belongs to `user`.
+fn locate<'a>(name: &'a str, user: Option<&'a str>) -> (Option<&'a str>, &'a str) {
+    match name.split_once('/') {
+        Some((owner, name)) => (Some(owner), name),
+        None => (user, name),
+    }
+}
+
+/// Smart playlists are filled from their owner's play history.
+async fn evaluate_smart(
+    playlist: &SmartPlaylist,
+    owner: Option<&str>,
+    index: &SongIndex,
+    history: &Mutex<Histories>,
+) -> Result<Vec<SongMeta>> {
+    let query: Query = playlist.query.parse()?;
+    let history = history.lock().await;
+    Ok(smart::evaluate(
+        &query,
+        index,
+        history.of(owner).stats(),
+        unix_now(),
+    ))
+}
+
+fn summary(playlist: &PlaylistMeta) -> Playlist {
+    Playlist {
+        name: playlist.title.clone(),
+        len: playlist.entries.len(),
+        query: None,
+        shared: playlist.shared,
+        owner: None,
+    }
+}
+
+fn smart_summary(smart: &SmartPlaylist, len: usize) -> Playlist {
+    Playlist {
+        name: smart.title.clone(),
+        len,
+        query: Some(smart.query.clone()),
+        shared: smart.shared,
+        owner: None,
+    }
+}
+
+async fn save_playlist(user: Option<&str>, playlist: &PlaylistMeta) -> Result<()> {
+    let file_path = playlist_path(user, playlist.title.clone())?;
+    fs::create_dir_all(playlists_dir(user)?).await?;
+    let data = serde_json::to_string_pretty(playlist)?;
+    fs::write(file_path, data).await?;
+    Ok(())
+}
+
+async fn save_smart_playlist(user: Option<&str>, smart: &SmartPlaylist) -> Result<()> {
+    let data = serde_json::to_string_pretty(smart)?;
+    fs::create_dir_all(playlists_dir(user)?).await?;
+    fs::write(smart_playlist_path(user, smart.title.clone())?, data).await?;
+    Ok(())
+}
+
+pub async fn get_playlist(user: Option<&str>, name: String) -> Result<PlaylistMeta> {
+    let file_path = playlist_path(user, name.clone())?;
+    let data = fs::read_to_string(file_path)
+        .await
+        .map_err(|_| anyhow::anyhow!("No playlist named {name}"))?;
+    parse_playlist(&data)
+}
+
+fn parse_playlist(data: &str) -> Result<PlaylistMeta> {
+    if let Ok(playlist) = serde_json::from_str::<PlaylistMeta>(data) {
+        return Ok(playlist);
+    }
+    let legacy: LegacyPlaylist = serde_json::from_str(data)?;
+    Ok(PlaylistMeta {
+        title: legacy.title,
+        entries: legacy.songs.iter().map(PlaylistEntry::from).collect(),
+        shared: false,
+    })
+}
+
+/// Loads a playlist and resolves its entries against the index, returning
+/// the songs along with their positions in it, and the entries that couldn't
+/// be found with theirs. Entries found through their title and artist hint
+/// are relinked on disk when the playlist is the user's own. Another user's
+/// playlist can be loaded as `owner/name` once they've shared it.
+pub async fn get_playlist_songs(
+    name: String,
+    user: Option<&str>,
+    index: &SongIndex,
+    history: &Mutex<Histories>,
+) -> Result<(Vec<SongMeta>, Vec<usize>, Vec<(usize, PlaylistEntry)>)> {
+    let (owner, title) = locate(&name, user);
+    let own = same_user(owner, user);
+
+    if let Some(smart) = get_smart_playlist(owner, title.to_string()).await?
+        && (own || smart.shared)
+    {
+        let songs = evaluate_smart(&smart, owner, index, history).await?;
+        let positions = (0..songs.len()).collect();
+        return Ok((songs, positions, Vec::new()));
+    }
+
+    let mut playlist = get_playlist(owner, title.to_string())
+        .await
+        .map_err(|_| anyhow::anyhow!("No playlist named {name}"))?;
+    if !own && !playlist.shared {
+        return Err(anyhow::anyhow!("No playlist named {name}"));
+    }
+    let mut songs = Vec::new();
+    let mut positions = Vec::new();
+    let mut missing = Vec::new();
+    let mut relinked = false;
+
+    for (position, entry) in playlist.entries.iter_mut().enumerate() {
+        if let Some(song) = index.get(&entry.id) {
+            songs.push(song.clone());
+            positions.push(position);
+        } else if let Some(song) = find_by_hint(entry, index) {
+            tracing::info!("Relinked {} to {}", entry.title, song.path.display());
+            *entry = PlaylistEntry::from(song);
+            songs.push(song.clone());
+            positions.push(position);
+            relinked = true;
+        } else {
+            missing.push((position, entry.clone()));
+        }
+    }
+
+    // shared playlists are read-only to everyone else
+    if relinked && own {
+        save_playlist(owner, &playlist).await?;
+    }
+    Ok((songs, positions, missing))
+}
+
+fn find_by_hint<'a>(entry: &PlaylistEntry, index: &'a SongIndex) -> Option<&'a SongMeta> {
+    let title = entry.title.to_lowercase();
+    let artists: Vec<String> = entry.artists.iter().map(|a| a.to_lowercase()).collect();
+
+    index.values().find(|song| {
+        song.title.to_lowercase() == title
+            && (artists.is_empty()
+                || song
+                    .artists
+                    .iter()
+                    .any(|a| artists.contains(&a.to_lowercase())))
+    })
+}
+
+pub fn entries_for(ids: Vec<Uuid>, index: &SongIndex) -> Result<Vec<PlaylistEntry>> {
+    ids.iter()
+        .map(|id| {
+            index
+                .get(id)
+                .map(PlaylistEntry::from)
+                .ok_or_else(|| anyhow::anyhow!("Track {id} is not in the index"))
+        })
+        .collect()
+}
+
+/// The user's own playlists, followed by the ones other users have shared.
+pub async fn get_all_playlists(
+    user: Option<&str>,
+    index: &SongIndex,
+    history: &Mutex<Histories>,
+) -> Result<Vec<Playlist>> {
+    let mut result = list_playlists(user, index, history).await?;
+
+    for owner in known_users().await? {
+        if same_user(Some(&owner), user) {
+            continue;
+        }
+        let playlists = list_playlists(Some(&owner), index, history).await?;
+        result.extend(
+            playlists
+                .into_iter()
+                .filter(|p| p.shared)
+                .map(|p| Playlist {
+                    owner: Some(owner.clone()),
+                    ..p
+                }),
+        );
+    }
+
+    Ok(result)
+}
+
+async fn list_playlists(
+    user: Option<&str>,
+    index: &SongIndex,
+    history: &Mutex<Histories>,
+) -> Result<Vec<Playlist>> {
+    // nothing has been saved yet
+    let mut dir = match fs::read_dir(playlists_dir(user)?).await {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
     let mut result = vec![];
 
     while let Some(entry) = dir.next_entry().await? {
         let path = entry.path();
-        if path.extension().and_then(|s| s.to_str()) == Some("json") {
-            let data = fs::read_to_string(&path).await?;
-            let playlist: PlaylistMeta = serde_json::from_str(&data)?;
-            result.push(Playlist {
-                name: playlist.title,
-                len: playlist.songs.len(),
-            });
+        match path.extension().and_then(|s| s.to_str()) {
+            Some("json") => {
+                let data = fs::read_to_string(&path).await?;
+                result.push(summary(&parse_playlist(&data)?));
+            }
+            Some("smart") => {
+                let data = fs::read_to_string(&path).await?;
+                let smart: SmartPlaylist = serde_json::from_str(&data)?;
+                let len = match evaluate_smart(&smart, user, index, history).await {
+                    Ok(songs) => songs.len(),
+                    Err(e) => {
+                        tracing::warn!("Smart playlist {} is invalid: {e}", smart.title);
+                        0
+                    }
+                };
+                result.push(smart_summary(&smart, len));
+            }
+            _ => {}
         }
     }
 
     Ok(result)
 }
 
-pub async fn create_playlist(title: String, songs: Vec<SongMeta>) -> Result<()> {
-    let playlists_dir = dirs::config_dir()
-        .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?
-        .join("musicman")
-        .join("playlists");
+pub async fn create_playlist(
+    title: String,
+    user: Option<&str>,
+    ids: Vec<Uuid>,
+    index: &SongIndex,
+) -> Result<Playlist> {
+    if get_smart_playlist(user, title.clone()).await?.is_some() {
+        return Err(anyhow::anyhow!("{title} is a smart playlist"));
+    }
+    let playlist = PlaylistMeta {
+        title: sanitize(title),
+        entries: entries_for(ids, index)?,
+        shared: false,
+    };
+    save_playlist(user, &playlist).await?;
 
-    fs::create_dir_all(&playlists_dir).await?;
+    Ok(summary(&playlist))
+}
 
-    let playlist = PlaylistMeta {
+/// Saves a playlist that's filled from `query` each time it's loaded.
+pub async fn create_smart_playlist(
+    title: String,
+    user: Option<&str>,
+    query: String,
+    index: &SongIndex,
+    history: &Mutex<Histories>,
+) -> Result<Playlist> {
+    if fs::try_exists(playlist_path(user, title.clone())?).await? {
+        return Err(anyhow::anyhow!("A playlist named {title} already exists"));
+    }
+    let smart = SmartPlaylist {
         title: sanitize(title),
-        songs,
+        query,
+        shared: false,
     };
+    let len = evaluate_smart(&smart, user, index, history).await?.len();
+    save_smart_playlist(user, &smart).await?;
 
-    let file_path = playlists_dir.join(format!("{}.json", playlist.title));
-    let data = serde_json::to_string_pretty(&playlist)?;
-    fs::write(file_path, data).await?;
+    Ok(smart_summary(&smart, len))
+}
+
+pub async fn rename_playlist(
+    name: String,
+    new_name: String,
+    user: Option<&str>,
+    index: &SongIndex,
+    history: &Mutex<Histories>,
+) -> Result<Playlist> {
+    if playlist_exists(user, new_name.clone()).await? {
+        return Err(anyhow::anyhow!(
+            "A playlist named {new_name} already exists"
+        ));
+    }
+
+    if let Some(mut smart) = get_smart_playlist(user, name.clone()).await? {
+        smart.title = sanitize(new_name);
+        let len = evaluate_smart(&smart, user, index, history).await?.len();
+        save_smart_playlist(user, &smart).await?;
+        fs::remove_file(smart_playlist_path(user, name)?).await?;
+        return Ok(smart_summary(&smart, len));
+    }
+
+    let mut playlist = get_playlist(user, name.clone()).await?;
+    playlist.title = sanitize(new_name);
+    save_playlist(user, &playlist).await?;
+    fs::remove_file(playlist_path(user, name)?).await?;
 
+    Ok(summary(&playlist))
+}
+
+pub async fn delete_playlist(name: String, user: Option<&str>) -> Result<()> {
+    if fs::remove_file(playlist_path(user, name.clone())?)
+        .await
+        .is_err()
+    {
+        fs::remove_file(smart_playlist_path(user, name.clone())?)
+            .await
+            .map_err(|_| anyhow::anyhow!("No playlist named {name}"))?;
+    }
     Ok(())
 }
+
+/// Shares a playlist read-only with the server's other users, or stops
+/// sharing it.
+pub async fn share_playlist(
+    name: String,
+    user: Option<&str>,
+    shared: bool,
+    index: &SongIndex,
+    history: &Mutex<Histories>,
+) -> Result<Playlist> {
+    if user.is_none() {
+        return Err(anyhow::anyhow!("Log in to share playlists"));
+    }
+
+    if let Some(mut smart) = get_smart_playlist(user, name.clone()).await? {
+        smart.shared = shared;
+        save_smart_playlist(user, &smart).await?;
+        let len = evaluate_smart(&smart, user, index, history)
+            .await
+            .map_or(0, |songs| songs.len());
+        return Ok(smart_summary(&smart, len));
+    }
+
+    let mut playlist = get_playlist(user, name).await?;
+    playlist.shared = shared;
+    save_playlist(user, &playlist).await?;
+
+    Ok(summary(&playlist))
+}
+
+/// Creates a playlist from another player's playlist file. Returns the new
+/// playlist and the entries that couldn't be matched to the index.
+pub async fn import_playlist(
+    name: String,
+    user: Option<&str>,
+    format: PlaylistFormat,
+    contents: &str,
+    index: &SongIndex,
+) -> Result<(Playlist, Vec<String>)> {
+    if playlist_exists(user, name.clone()).await? {
+        return Err(anyhow::anyhow!("A playlist named {name} already exists"));
+    }
+    let entries = formats::parse(format, contents)?;
+    let (songs, unmatched) = formats::resolve(&entries, index);
+    let ids = songs.iter().map(|s| s.id).collect();
+    let playlist = create_playlist(name, user, ids, index).await?;
+    Ok((playlist, unmatched))
+}
+
+pub async fn export_playlist(
+    name: String,
+    user: Option<&str>,
+    format: PlaylistFormat,
+    index: &SongIndex,
+    history: &Mutex<Histories>,
+) -> Result<String> {
+    let (songs, _, _) = get_playlist_songs(name.clone(), user, index, history).await?;
+    Ok(formats::export(format, &name, &songs))
+}
+
+/// Loads a playlist, applies `edit` to its songs and writes it back.
+pub async fn update_playlist<F>(name: String, user: Option<&str>, edit: F) -> Result<Playlist>
+where
+    F: FnOnce(&mut Vec<PlaylistEntry>) -> Result<()>,
+{
+    if get_smart_playlist(user, name.clone()).await?.is_some() {
+        return Err(anyhow::anyhow!(
+            "{name} is a smart playlist and can't be edited by hand"
+        ));
+    }
+    let mut playlist = get_playlist(user, name).await?;
+    edit(&mut playlist.entries)?;
+    save_playlist(user, &playlist).await?;
+
+    Ok(summary(&playlist))
+}
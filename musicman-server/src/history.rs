@@ -0,0 +1,189 @@
+use crate::{
+    helpers::{data_dir, user_key},
+    types::*,
+};
+use anyhow::Result;
+use musicman_protocols::*;
+use std::{collections::HashMap, path::PathBuf, sync::LazyLock};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+/// Every user's play history. Plays of clients that haven't logged in are
+/// kept under `None`.
+#[derive(Default)]
+pub struct Histories {
+    users: HashMap<Option<String>, History>,
+}
+
+static NO_HISTORY: LazyLock<History> = LazyLock::new(History::default);
+
+impl Histories {
+    pub async fn load() -> Result<Self> {
+        let mut users = HashMap::new();
+        let mut anonymous = History::load(None).await?;
+        anonymous.import_totals().await?;
+        users.insert(None, anonymous);
+        if let Ok(mut dir) = tokio::fs::read_dir(data_dir(None)?.join("users")).await {
+            while let Some(entry) = dir.next_entry().await? {
+                let user = entry.file_name().to_string_lossy().to_string();
+                let history = History::load(Some(&user)).await?;
+                users.insert(Some(user), history);
+            }
+        }
+        let plays: usize = users.values().map(|h| h.events.len()).sum();
+        tracing::info!("Loaded {plays} plays.");
+        Ok(Histories { users })
+    }
+
+    pub fn of(&self, user: Option<&str>) -> &History {
+        self.users.get(&user.map(user_key)).unwrap_or(&NO_HISTORY)
+    }
+
+    /// Logs a play in the history of the user that made it.
+    pub async fn record(&mut self, event: PlayEvent) -> Result<()> {
+        let user = event.user.as_deref().map(user_key);
+        let history = self.users.entry(user.clone()).or_default();
+        history.record(user.as_deref(), event).await
+    }
+}
+
+/// Every logged play of one user, oldest first, along with per-track totals
+/// derived from them. Plays are appended to `history.jsonl` as they happen.
+#[derive(Default)]
+pub struct History {
+    events: Vec<PlayEvent>,
+    stats: StatsIndex,
+}
+
+fn history_file(user: Option<&str>) -> Result<PathBuf> {
+    Ok(data_dir(user)?.join("history.jsonl"))
+}
+
+impl History {
+    async fn load(user: Option<&str>) -> Result<Self> {
+        let data = match tokio::fs::read_to_string(history_file(user)?).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut history = Self::default();
+        for (n, line) in data.lines().enumerate() {
+            match serde_json::from_str::<PlayEvent>(line) {
+                Ok(event) => history.add(event),
+                Err(e) => tracing::warn!("Skipping history line {}: {e}", n + 1),
+            }
+        }
+        Ok(history)
+    }
+
+    /// Adds the per-track totals kept in `stats.json` before plays were
+    /// logged one by one. They carry no times of their own, so they only
+    /// count towards the stats, not the history or top lists. The file is
+    /// left as it is and never written again.
+    async fn import_totals(&mut self) -> Result<()> {
+        let data = match tokio::fs::read_to_string(data_dir(None)?.join("stats.json")).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let totals: StatsIndex = serde_json::from_str(&data)?;
+        for (track_id, totals) in totals {
+            let entry = self.stats.entry(track_id).or_default();
+            entry.plays += totals.plays;
+            entry.last_played = entry.last_played.max(totals.last_played);
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> &StatsIndex {
+        &self.stats
+    }
+
+    async fn record(&mut self, user: Option<&str>, event: PlayEvent) -> Result<()> {
+        let mut line = serde_json::to_string(&event)?;
+        line.push('\n');
+        let file = history_file(user)?;
+        if let Some(dir) = file.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+
+        self.add(event);
+        Ok(())
+    }
+
+    fn add(&mut self, event: PlayEvent) {
+        let entry = self.stats.entry(event.track_id).or_default();
+        entry.plays += 1;
+        entry.last_played = entry.last_played.max(Some(event.time));
+        self.events.push(event);
+    }
+
+    /// The latest `limit` plays of tracks still in the index, newest first.
+    pub fn recent(&self, limit: usize, index: &SongIndex) -> Vec<PlayedSong> {
+        self.events
+            .iter()
+            .rev()
+            .filter_map(|event| {
+                index.get(&event.track_id).map(|song| PlayedSong {
+                    song: song.clone(),
+                    time: event.time,
+                    client: event.client.clone(),
+                })
+            })
+            .take(limit)
+            .collect()
+    }
+
+    /// Play counts per track since `since`.
+    fn counts(&self, since: u64) -> HashMap<uuid::Uuid, u32> {
+        let mut counts = HashMap::new();
+        for event in self.events.iter().filter(|e| e.time >= since) {
+            *counts.entry(event.track_id).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub fn top_tracks(&self, since: u64, limit: usize, index: &SongIndex) -> Vec<SongPlays> {
+        let mut top: Vec<SongPlays> = self
+            .counts(since)
+            .into_iter()
+            .filter_map(|(id, plays)| {
+                index.get(&id).map(|song| SongPlays {
+                    song: song.clone(),
+                    plays,
+                })
+            })
+            .collect();
+        top.sort_by(|a, b| b.plays.cmp(&a.plays).then(a.song.title.cmp(&b.song.title)));
+        top.truncate(limit);
+        top
+    }
+
+    pub fn top_artists(&self, since: u64, limit: usize, index: &SongIndex) -> Vec<ArtistPlays> {
+        let mut by_artist: HashMap<&str, u32> = HashMap::new();
+        for (id, plays) in self.counts(since) {
+            if let Some(song) = index.get(&id) {
+                for artist in &song.artists {
+                    *by_artist.entry(artist).or_insert(0) += plays;
+                }
+            }
+        }
+
+        let mut top: Vec<ArtistPlays> = by_artist
+            .into_iter()
+            .map(|(name, plays)| ArtistPlays {
+                name: name.to_string(),
+                plays,
+            })
+            .collect();
+        top.sort_by(|a, b| b.plays.cmp(&a.plays).then(a.name.cmp(&b.name)));
+        top.truncate(limit);
+        top
+    }
+}
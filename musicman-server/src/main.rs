@@ -1,22 +1,47 @@
-use std::{env, process::exit, sync::Arc};
+use std::{collections::HashMap, env, net::SocketAddr, process::exit, sync::Arc, time::Instant};
 
-use musicman_protocols::{PlaylistRequest, PlaylistResponse, Request, Response};
+use musicman_protocols::{PlayEvent, Request, Response};
 use tokio::{
-    io::AsyncReadExt,
-    net::{TcpListener, tcp::OwnedReadHalf},
+    io::{AsyncReadExt, AsyncWrite},
+    net::{TcpListener, TcpStream, UnixListener},
     sync::{Mutex, mpsc},
 };
+use tokio_rustls::TlsAcceptor;
 
+mod admin;
+mod auth;
+mod browse;
+mod config;
+mod counters;
+mod formats;
 mod handlers;
 mod helpers;
+mod history;
+mod metrics;
+mod radio;
+mod search;
+mod sessions;
+mod shutdown;
+mod smart;
+mod streams;
+mod sync;
+mod tls;
 mod types;
+mod unix_socket;
+use handlers::Start;
+use history::Histories;
+use streams::Streams;
 use tracing::info;
-use types::State;
+use types::{Connection, Context, Library, ReadSocket, State, WriteSocket};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
 
+    if env::args().nth(1).as_deref() == Some("hash-password") {
+        return print_password_hash();
+    }
+
     let Ok(port) = env::args()
         .nth(1)
         .unwrap_or_else(|| "4000".to_string())
@@ -26,28 +51,215 @@ async fn main() -> anyhow::Result<()> {
         exit(1)
     };
 
-    let Ok(listener) = TcpListener::bind(("0.0.0.0", port)).await else {
-        tracing::error!("Could not bind to port: {port}.");
+    let config = config::load_config().await?;
+    let tcp = match config.listen.tcp {
+        true => match TcpListener::bind(("0.0.0.0", port)).await {
+            Ok(listener) => Some(listener),
+            Err(_) => {
+                tracing::error!("Could not bind to port: {port}.");
+                exit(1)
+            }
+        },
+        false => None,
+    };
+    let unix = match &config.listen.unix {
+        Some(path) => match unix_socket::bind(path, config.listen.unix_mode).await {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                tracing::error!("Could not listen on {}: {e:#}", path.display());
+                exit(1)
+            }
+        },
+        None => None,
+    };
+    if tcp.is_none() && unix.is_none() {
+        tracing::error!("Nothing to listen on: set a unix socket or turn tcp back on.");
         exit(1)
+    }
+    let metrics = match &config.metrics {
+        Some(metrics) => match TcpListener::bind(metrics.listen).await {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                tracing::error!("Could not serve metrics on {}: {e}", metrics.listen);
+                exit(1)
+            }
+        },
+        None => None,
+    };
+    let tls = match &config.tls {
+        Some(tls) => match tls::acceptor(tls) {
+            Ok(acceptor) => Some(acceptor),
+            Err(e) => {
+                tracing::error!("Could not set up TLS: {e:#}");
+                exit(1)
+            }
+        },
+        None => None,
     };
+    // so the first login for an unknown user isn't the slow one
+    tokio::task::spawn_blocking(auth::prepare);
     tracing::info!("Started index generation.");
 
-    helpers::generate_index(&dirs::home_dir().unwrap().join("Music")).await?;
-    tracing::info!("Server listening on 0.0.0.0:{port}");
+    let index = tokio::task::spawn_blocking(|| {
+        helpers::generate_index(&helpers::music_dir(), false, |_, _| {})
+    })
+    .await??;
+    let socket_path = config.listen.unix.clone();
+    let (stop, shutdown) = streams::cancel_pair();
+    let ctx = Arc::new(Context {
+        library: std::sync::RwLock::new(Arc::new(Library::new(index))),
+        history: Mutex::new(Histories::load().await?),
+        sessions: Mutex::new(sessions::load().await?),
+        clients: Mutex::new(HashMap::new()),
+        connections: Mutex::new(HashMap::new()),
+        config: std::sync::RwLock::new(Arc::new(config)),
+        started: Instant::now(),
+        shutdown,
+    });
+    config::reload_on_hangup(ctx.clone())?;
+    sessions::save_periodically(ctx.clone());
+    if let Some(listener) = metrics {
+        if let Ok(addr) = listener.local_addr() {
+            tracing::info!("Serving metrics on http://{addr}/metrics");
+        }
+        tokio::spawn(metrics::serve(listener, ctx.clone()));
+    }
+
+    let unix_task = unix.map(|listener| {
+        if let Some(path) = &socket_path {
+            tracing::info!("Server listening on {}", path.display());
+        }
+        tokio::spawn(serve_unix(listener, ctx.clone()))
+    });
+    let serving = async {
+        match tcp {
+            Some(listener) => {
+                tracing::info!("Server listening on 0.0.0.0:{port}");
+                serve_tcp(listener, tls, ctx.clone()).await
+            }
+            None => std::future::pending().await,
+        }
+    };
+    // Dropping the TCP listener along with `serving` stops new connections.
+    tokio::select! {
+        res = serving => res?,
+        res = shutdown::signalled() => res?,
+    }
+
+    tracing::info!("Shutting down.");
+    if let Some(task) = unix_task {
+        task.abort();
+    }
+    stop.cancel();
+    shutdown::run(&ctx).await;
+    if let Some(path) = socket_path {
+        let _ = std::fs::remove_file(path);
+    }
+    tracing::info!("Shut down.");
+    Ok(())
+}
 
+async fn serve_tcp(
+    listener: TcpListener,
+    tls: Option<TlsAcceptor>,
+    ctx: Arc<Context>,
+) -> anyhow::Result<()> {
     loop {
         let (socket, addr) = listener.accept().await?;
         tracing::info!("New client: {:?}", addr);
 
+        let ctx = ctx.clone();
+        let tls = tls.clone();
         tokio::spawn(async move {
-            if let Err(e) = handle_client(socket).await {
+            let result = match open(socket, &addr, tls).await {
+                Ok(Some((read, write))) => handle_client(read, write, addr.to_string(), ctx).await,
+                Ok(None) => Ok(()),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
                 tracing::error!("Error with client {:?}: {:?}", addr, e);
             }
         });
     }
 }
 
-async fn read_request(read: &mut OwnedReadHalf) -> anyhow::Result<Request> {
+/// Unix socket clients are local, so they skip TLS. The socket's
+/// permissions decide who gets this far.
+async fn serve_unix(listener: UnixListener, ctx: Arc<Context>) {
+    loop {
+        let socket = match listener.accept().await {
+            Ok((socket, _)) => socket,
+            Err(e) => {
+                tracing::error!("Could not accept on the unix socket: {e}");
+                continue;
+            }
+        };
+        let client = unix_socket::client_id();
+        match socket.peer_cred() {
+            Ok(cred) => tracing::info!("New client: {client} (uid {})", cred.uid()),
+            Err(_) => tracing::info!("New client: {client}"),
+        }
+
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            let (read, write) = socket.into_split();
+            let write: WriteSocket = Arc::new(Mutex::new(Box::new(write)));
+            if let Err(e) = handle_client(Box::new(read), write, client.clone(), ctx).await {
+                tracing::error!("Error with client {client}: {e:?}");
+            }
+        });
+    }
+}
+
+/// Splits the connection, going through TLS if the client opened with a
+/// handshake. `None` if the connection was refused.
+async fn open(
+    socket: TcpStream,
+    addr: &SocketAddr,
+    tls: Option<TlsAcceptor>,
+) -> anyhow::Result<Option<(ReadSocket, WriteSocket)>> {
+    let handshake = tls::is_handshake(&socket).await?;
+    let (read, write): (ReadSocket, Box<dyn AsyncWrite + Send + Unpin>) = match (tls, handshake) {
+        (Some(acceptor), true) => {
+            let (read, write) = tokio::io::split(acceptor.accept(socket).await?);
+            (Box::new(read), Box::new(write))
+        }
+        (None, true) => {
+            tracing::warn!("{addr} asked for TLS, which isn't set up.");
+            return Ok(None);
+        }
+        (Some(_), false) if !tls::plain_allowed(addr) => {
+            tracing::warn!("Refused a plain connection from {addr}.");
+            let (_, write) = socket.into_split();
+            let write: WriteSocket = Arc::new(Mutex::new(Box::new(write)));
+            let res = Response::Error {
+                message: "This server only accepts TLS connections".to_string(),
+            };
+            helpers::send_to_client(&write, &res).await?;
+            return Ok(None);
+        }
+        _ => {
+            let (read, write) = socket.into_split();
+            (Box::new(read), Box::new(write))
+        }
+    };
+    Ok(Some((read, Arc::new(Mutex::new(write)))))
+}
+
+/// Reads a password from stdin and prints the hash to put in `server.toml`.
+fn print_password_hash() -> anyhow::Result<()> {
+    eprint!("Password: ");
+    let mut password = String::new();
+    std::io::stdin().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        anyhow::bail!("Empty password");
+    }
+    println!("{}", auth::hash_password(password)?);
+    Ok(())
+}
+
+async fn read_request(read: &mut ReadSocket) -> anyhow::Result<Request> {
     let mut len_buf = [0u8; 4];
     read.read_exact(&mut len_buf).await?;
     let msg_len = u32::from_be_bytes(len_buf) as usize;
@@ -58,93 +270,309 @@ async fn read_request(read: &mut OwnedReadHalf) -> anyhow::Result<Request> {
     Ok(req)
 }
 
-async fn handle_client(socket: tokio::net::TcpStream) -> anyhow::Result<()> {
-    let (mut read, write) = socket.into_split();
-    let write = Arc::new(Mutex::new(write));
+/// The session this client is attached to, if it plays in sync.
+async fn synced_session(state: &State, ctx: &Context) -> Option<String> {
+    let name = state.session.as_ref()?;
+    let sessions = ctx.sessions.lock().await;
+    sessions
+        .get(name)
+        .is_some_and(|session| session.sync)
+        .then(|| name.clone())
+}
 
-    let index = helpers::load_index().await?;
+async fn handle_client(
+    mut read: ReadSocket,
+    write: WriteSocket,
+    client: String,
+    ctx: Arc<Context>,
+) -> anyhow::Result<()> {
     let mut state = State {
-        current_stream_cancel: None,
+        streams: Streams::default(),
+        session: None,
+        name: None,
+        track: None,
+        user: None,
+        held_secs: 0,
     };
+    let (kick, mut kicked) = mpsc::channel::<()>(1);
+    ctx.connections.lock().await.insert(
+        client.clone(),
+        Connection {
+            name: None,
+            session: None,
+            track: None,
+            connected: helpers::unix_now(),
+            kick,
+        },
+    );
 
+    let result = serve_client(&mut read, &write, &client, &ctx, &mut state, &mut kicked).await;
+
+    ctx.connections.lock().await.remove(&client);
+    if let Some(name) = state.session {
+        sessions::detach(&mut *ctx.sessions.lock().await, &name, &write).await;
+        sessions::flush(&ctx.sessions).await;
+    }
+    if let Some(name) = state.name {
+        handlers::unregister(&ctx, &name, &write).await;
+    }
+    info!("Client Disconnected.");
+    result
+}
+
+/// Answers requests until the client goes away, is kicked or the server
+/// shuts down. A failed write ends it early, so `handle_client` cleans up
+/// after it either way.
+async fn serve_client(
+    read: &mut ReadSocket,
+    write: &WriteSocket,
+    client: &str,
+    ctx: &Arc<Context>,
+    state: &mut State,
+    kicked: &mut mpsc::Receiver<()>,
+) -> anyhow::Result<()> {
+    let mut failed_logins = 0;
     loop {
         // Deserialize request
-        let maybe_request = read_request(&mut read).await;
+        let maybe_request = tokio::select! {
+            req = read_request(read) => req,
+            _ = kicked.recv() => {
+                info!("Disconnected by an admin.");
+                break;
+            }
+            _ = ctx.shutdown.cancelled() => {
+                let _ = helpers::send_to_client(write, &Response::ShuttingDown).await;
+                state.streams.finish(shutdown::GRACE).await;
+                break;
+            }
+        };
         if maybe_request.is_err() {
             break;
         }
         let request = maybe_request.unwrap();
 
-        tracing::info!("Requested: {:?}", request);
+        match &request {
+            // keep secrets out of the logs
+            Request::Admin { request, .. } => tracing::info!("Requested: Admin({request:?})"),
+            Request::Login(_) => tracing::info!("Requested: Login"),
+            _ => tracing::info!("Requested: {:?}", request),
+        }
+        counters::count_request(&request);
+        if let Err(e) = auth::check(&request, state.user.as_ref(), &ctx.config().auth) {
+            helpers::send_to_client(write, &Response::Denied(e)).await?;
+            continue;
+        }
+        if let Request::Play { track_id }
+        | Request::Resume { track_id, .. }
+        | Request::Continue { track_id, .. } = request
+        {
+            state.track = Some(track_id);
+        }
+
+        let library = ctx.library();
+        let index = library.songs();
+        let user = state.user.as_ref().map(|u| u.name.clone());
+        let play = |track_id| PlayEvent {
+            time: 0,
+            track_id,
+            client: client.to_string(),
+            user: user.clone(),
+        };
         match request {
-            Request::Play { track_id } => {
-                if let Some(_) = &state.current_stream_cancel {
-                    state.current_stream_cancel = None;
-                }
-                match helpers::get_track_file(&track_id, &index).await {
-                    Ok(file) => {
-                        let (cancel_tx, cancel_rx) = mpsc::channel::<()>(4);
-                        let write_copy = write.clone();
-                        tokio::spawn(async move {
-                            tracing::info!("Started streaming.");
-                            if let Err(e) = handlers::stream_file(
-                                file,
-                                track_id.clone(),
-                                &write_copy,
-                                cancel_rx,
-                            )
-                            .await
-                            {
-                                tracing::error!("Streaming file failed. {e}")
-                            }
-                        });
-                        state.current_stream_cancel = Some(cancel_tx);
+            Request::Login(credentials) => {
+                let res = match auth::login(credentials, &ctx.config().auth).await {
+                    Ok(user) => {
+                        info!("Logged in as {}.", user.name);
+                        let res = Response::LoggedIn {
+                            user: user.name.clone(),
+                            permission: user.permission,
+                        };
+                        state.user = Some(user);
+                        res
                     }
                     Err(e) => {
+                        tracing::warn!("Failed login.");
+                        failed_logins += 1;
+                        Response::Denied(e)
+                    }
+                };
+                helpers::send_to_client(write, &res).await?;
+                if failed_logins >= auth::MAX_LOGIN_ATTEMPTS {
+                    info!("Too many failed logins.");
+                    break;
+                }
+            }
+            Request::Play { track_id } | Request::Resume { track_id, .. } => {
+                let position = match request {
+                    Request::Resume { position, .. } => position,
+                    _ => 0,
+                };
+                state.streams.stop_all().await;
+                if let Some(name) = synced_session(state, ctx).await {
+                    sync::play(ctx, &name, track_id, position).await;
+                } else if let Some(stream) = handlers::start_stream(
+                    play(track_id),
+                    Start::Secs(position),
+                    false,
+                    state,
+                    index,
+                    ctx,
+                    write,
+                )
+                .await?
+                {
+                    state.streams.insert(stream);
+                }
+            }
+            Request::Continue {
+                track_id,
+                from_chunk,
+            } => {
+                // attaching to a synced session catches the client up already
+                if synced_session(state, ctx).await.is_none() {
+                    state.streams.stop_all().await;
+                    if let Some(stream) = handlers::start_stream(
+                        play(track_id),
+                        Start::Chunk(from_chunk),
+                        false,
+                        state,
+                        index,
+                        ctx,
+                        write,
+                    )
+                    .await?
+                    {
+                        state.streams.insert(stream);
+                    }
+                }
+            }
+            Request::Prefetch { track_id } => {
+                // synced sessions move on to the next track by themselves
+                if synced_session(state, ctx).await.is_none() {
+                    if state.streams.is_full() {
                         let res = Response::Error {
-                            message: "Track not found".to_string(),
+                            message: format!(
+                                "At most {} streams can run at once",
+                                streams::MAX_STREAMS
+                            ),
                         };
-                        tracing::warn!("{e}");
-                        helpers::send_to_client(&write, &res).await?;
+                        helpers::send_to_client(write, &res).await?;
+                    } else if let Some(stream) = handlers::start_stream(
+                        play(track_id),
+                        Start::Secs(0),
+                        true,
+                        state,
+                        index,
+                        ctx,
+                        write,
+                    )
+                    .await?
+                    {
+                        state.streams.insert(stream);
                     }
-                };
+                }
             }
+            // streams that already ended need no stopping
+            Request::Stop { stream_id } => state.streams.stop(stream_id).await,
+            Request::HoldBack { secs } => state.held_secs = secs as u64,
             Request::Search(s_type) => {
-                let data = handlers::handle_search(s_type, &index).await;
-                let res = Response::SearchResults(data);
-                helpers::send_to_client(&write, &res).await?;
-            }
-            Request::Playlist(plreq) => match plreq {
-                PlaylistRequest::List => {
-                    let playlists = helpers::get_all_playlists().await?;
-                    let plres = PlaylistResponse::Playlists(playlists);
-                    let res = Response::Playlist(plres);
-                    helpers::send_to_client(&write, &res).await?;
+                let started = Instant::now();
+                let res =
+                    handlers::handle_search(s_type, &library, &ctx.history, user.as_deref()).await;
+                counters::observe_search(started.elapsed());
+                helpers::send_to_client(write, &res).await?;
+            }
+            Request::Browse(breq) => {
+                let res = handlers::handle_browse(breq, index);
+                helpers::send_to_client(write, &res).await?;
+            }
+            Request::History(hreq) => {
+                let res = {
+                    let history = ctx.history.lock().await;
+                    handlers::handle_history(hreq, index, history.of(user.as_deref()))
+                };
+                helpers::send_to_client(write, &res).await?;
+            }
+            Request::Radio {
+                seeds,
+                exclude,
+                count,
+            } => {
+                let songs = {
+                    let history = ctx.history.lock().await;
+                    radio::related(&seeds, &exclude, count, index, history.of(user.as_deref()))
+                };
+                helpers::send_to_client(write, &Response::Radio(songs)).await?;
+            }
+            Request::Session(sreq) => {
+                let res = handlers::handle_session(
+                    sreq,
+                    &mut state.session,
+                    user.as_deref(),
+                    ctx,
+                    index,
+                    write,
+                )
+                .await;
+                if let Some(res) = res {
+                    helpers::send_to_client(write, &res).await?;
                 }
-                PlaylistRequest::Get { name } => {
-                    let pl = helpers::get_playlist(name).await?;
-                    let plres = PlaylistResponse::Songs(pl.songs);
-                    let res = Response::Playlist(plres);
-                    helpers::send_to_client(&write, &res).await?;
+            }
+            Request::Register { name } => {
+                let res = handlers::handle_register(
+                    name,
+                    &mut state.name,
+                    user.as_deref(),
+                    ctx,
+                    client,
+                    write,
+                )
+                .await;
+                if let Some(res) = res {
+                    helpers::send_to_client(write, &res).await?;
                 }
-                PlaylistRequest::Create { name, songs } => {
-                    helpers::create_playlist(name, songs).await?;
+            }
+            Request::Control { target, command } => {
+                let from = match &state.name {
+                    Some(key) => sessions::display_name(key).to_string(),
+                    None => client.to_string(),
+                };
+                let res =
+                    handlers::handle_control(target, command, from, user.as_deref(), ctx).await;
+                if let Some(res) = res {
+                    helpers::send_to_client(write, &res).await?;
                 }
-            },
+            }
+            Request::Clients => {
+                let res = handlers::list_clients(ctx, user.as_deref()).await;
+                helpers::send_to_client(write, &res).await?;
+            }
+            Request::Admin { token, request } => {
+                admin::handle_admin(token, request, state.user.as_ref(), ctx, write).await?;
+            }
+            Request::Playlist(plreq) => {
+                let res =
+                    handlers::handle_playlist(plreq, user.as_deref(), index, &ctx.history).await;
+                helpers::send_to_client(write, &res).await?;
+            }
             Request::Meta { track_id } => {
-                if let Some(meta) = helpers::get_track_meta(&track_id, &index).await? {
+                if let Some(meta) = helpers::get_track_meta(&track_id, index).await? {
                     let res = Response::Meta(meta);
-                    helpers::send_to_client(&write, &res).await?;
+                    helpers::send_to_client(write, &res).await?;
                 } else {
                     let res = Response::Error {
                         message: "Track not found".to_string(),
                     };
-                    helpers::send_to_client(&write, &res).await?;
+                    helpers::send_to_client(write, &res).await?;
                 }
             }
         };
-    }
 
-    info!("Client Disconnected.");
+        if let Some(conn) = ctx.connections.lock().await.get_mut(client) {
+            conn.name = state.name.clone();
+            conn.session = state.session.clone();
+            conn.track = state.track;
+        }
+    }
     Ok(())
 }
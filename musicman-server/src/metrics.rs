@@ -0,0 +1,136 @@
+use crate::{counters, types::Context};
+use std::{fmt::Write as _, sync::Arc};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Longest request head read before giving up on a scrape.
+const MAX_HEAD: usize = 8192;
+
+/// Answers `GET /metrics` with the server's metrics in Prometheus' text
+/// format. Nothing else is served.
+pub async fn serve(listener: TcpListener, ctx: Arc<Context>) {
+    loop {
+        let socket = match listener.accept().await {
+            Ok((socket, _)) => socket,
+            Err(e) => {
+                tracing::error!("Could not accept a metrics scrape: {e}");
+                continue;
+            }
+        };
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(socket, &ctx).await {
+                tracing::debug!("Metrics scrape failed. {e}");
+            }
+        });
+    }
+}
+
+async fn respond(mut socket: TcpStream, ctx: &Context) -> anyhow::Result<()> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = socket.read(&mut buf).await?;
+        if n == 0 || head.len() > MAX_HEAD {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+
+    let (status, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render(ctx).await),
+        (Some("GET"), _) => ("404 Not Found", "Not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "Only GET is served\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await?;
+    Ok(())
+}
+
+async fn render(ctx: &Context) -> String {
+    let mut out = String::new();
+    let clients = ctx.connections.lock().await.len();
+    let songs = ctx.library().songs().len();
+
+    let gauges: [(&str, &str, f64); 4] = [
+        ("connected_clients", "Connected clients.", clients as f64),
+        (
+            "active_streams",
+            "Streams being sent.",
+            counters::active_streams() as f64,
+        ),
+        ("index_songs", "Songs in the index.", songs as f64),
+        (
+            "index_scan_duration_seconds",
+            "How long the last scan of the music directory took.",
+            counters::scan_duration().as_secs_f64(),
+        ),
+    ];
+    for (name, help, value) in gauges {
+        header(&mut out, name, "gauge", help);
+        let _ = writeln!(out, "musicman_{name} {value}");
+    }
+
+    let totals: [(&str, &str, u64); 3] = [
+        (
+            "sent_bytes_total",
+            "Bytes written to clients.",
+            counters::bytes_sent(),
+        ),
+        (
+            "streamed_bytes_total",
+            "Bytes of audio streamed.",
+            counters::streamed_bytes(),
+        ),
+        (
+            "decode_errors_total",
+            "Audio packets that failed to decode.",
+            counters::decode_errors(),
+        ),
+    ];
+    for (name, help, value) in totals {
+        header(&mut out, name, "counter", help);
+        let _ = writeln!(out, "musicman_{name} {value}");
+    }
+
+    header(
+        &mut out,
+        "requests_total",
+        "counter",
+        "Requests received, by type.",
+    );
+    for (kind, count) in counters::requests() {
+        let _ = writeln!(out, "musicman_requests_total{{type=\"{kind}\"}} {count}");
+    }
+
+    let name = "search_duration_seconds";
+    header(&mut out, name, "histogram", "Time taken by searches.");
+    let (counts, total) = counters::searches();
+    let bounds = counters::SEARCH_BUCKETS.map(|bound| bound.to_string());
+    let mut cumulative = 0;
+    for (bound, count) in bounds
+        .iter()
+        .map(String::as_str)
+        .chain(["+Inf"])
+        .zip(counts)
+    {
+        cumulative += count;
+        let _ = writeln!(out, "musicman_{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+    }
+    let _ = writeln!(out, "musicman_{name}_sum {}", total.as_secs_f64());
+    let _ = writeln!(out, "musicman_{name}_count {cumulative}");
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP musicman_{name} {help}");
+    let _ = writeln!(out, "# TYPE musicman_{name} {kind}");
+}
@@ -0,0 +1,102 @@
+use crate::{helpers::unix_now, history::History, search::normalize, types::*};
+use musicman_protocols::*;
+use std::hash::{BuildHasher, RandomState};
+use uuid::Uuid;
+
+/// Tracks played this recently are left out, even if that means fewer
+/// picks.
+const RECENT_SECS: u64 = 6 * 3600;
+/// Keeps one artist from taking over a batch.
+const MAX_PER_ARTIST: usize = 2;
+
+/// Picks up to `count` tracks that fit after `seeds`. Tracks score for
+/// sharing artists, album artist, album or genre with the seeds, for being
+/// from around the same year, and a little for being played often. A random
+/// nudge keeps repeated requests from returning the same list.
+pub fn related(
+    seeds: &[Uuid],
+    exclude: &[Uuid],
+    count: usize,
+    index: &SongIndex,
+    history: &History,
+) -> Vec<SongMeta> {
+    let seeds: Vec<&SongMeta> = seeds.iter().filter_map(|id| index.get(id)).collect();
+    if seeds.is_empty() {
+        return Vec::new();
+    }
+    let now = unix_now();
+    let jitter = RandomState::new();
+
+    let recent = |song: &SongMeta| {
+        history
+            .stats()
+            .get(&song.id)
+            .and_then(|s| s.last_played)
+            .is_some_and(|t| now.saturating_sub(t) < RECENT_SECS)
+    };
+
+    let mut scored: Vec<(f32, &SongMeta)> = index
+        .values()
+        .filter(|song| !exclude.contains(&song.id) && !seeds.iter().any(|s| s.id == song.id))
+        .filter(|song| !recent(song))
+        .map(|song| {
+            let plays = history.stats().get(&song.id).map_or(0, |s| s.plays);
+            let affinity =
+                seeds.iter().map(|seed| affinity(seed, song)).sum::<f32>() / seeds.len() as f32;
+            let nudge = (jitter.hash_one(song.id) % 1000) as f32 / 1000.0;
+            let score = affinity + 0.3 * (1.0 + plays as f32).ln() + 0.5 * nudge;
+            (score, song)
+        })
+        .collect();
+    scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    let mut picked: Vec<SongMeta> = Vec::new();
+    for (_, song) in &scored {
+        if picked.len() == count {
+            break;
+        }
+        let by_artist = picked
+            .iter()
+            .filter(|p| p.artists.iter().any(|a| song.artists.contains(a)))
+            .count();
+        if by_artist < MAX_PER_ARTIST {
+            picked.push((*song).clone());
+        }
+    }
+    // small libraries may not have enough artists to go around
+    for (_, song) in &scored {
+        if picked.len() == count {
+            break;
+        }
+        if !picked.iter().any(|p| p.id == song.id) {
+            picked.push((*song).clone());
+        }
+    }
+    picked
+}
+
+fn affinity(seed: &SongMeta, song: &SongMeta) -> f32 {
+    let same = |a: &str, b: &str| !a.is_empty() && normalize(a) == normalize(b);
+    let mut score = 0.0;
+
+    if seed
+        .artists
+        .iter()
+        .any(|a| song.artists.iter().any(|b| same(a, b)))
+    {
+        score += 3.0;
+    }
+    if same(&seed.album_artist, &song.album_artist) {
+        score += 2.0;
+    }
+    if same(&seed.album, &song.album) {
+        score += 1.0;
+    }
+    if same(&seed.genre, &song.genre) {
+        score += 2.0;
+    }
+    if let (Some(a), Some(b)) = (seed.year, song.year) {
+        score += 1.5 * (1.0 - a.abs_diff(b) as f32 / 10.0).max(0.0);
+    }
+    score
+}
@@ -0,0 +1,319 @@
+use crate::types::*;
+use musicman_protocols::*;
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Bound,
+};
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
+use uuid::Uuid;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Field {
+    Title,
+    Artist,
+    Album,
+}
+
+impl Field {
+    fn weight(self) -> f32 {
+        match self {
+            Field::Title => 3.0,
+            Field::Artist => 2.0,
+            Field::Album => 1.0,
+        }
+    }
+}
+
+struct Posting {
+    id: Uuid,
+    field: Field,
+}
+
+/// Token index over the library for ranked, typo-tolerant lookups. Built
+/// from a [`SongIndex`] and rebuilt along with it.
+#[derive(Default)]
+pub struct SearchIndex {
+    terms: BTreeMap<String, Vec<Posting>>,
+    /// The terms by length in characters, to find typos among.
+    by_len: HashMap<usize, Vec<String>>,
+}
+
+impl SearchIndex {
+    pub fn build(songs: &SongIndex) -> Self {
+        let mut terms: BTreeMap<String, Vec<Posting>> = BTreeMap::new();
+        let mut add = |text: &str, id: Uuid, field: Field| {
+            for token in tokenize(text) {
+                let postings = terms.entry(token).or_default();
+                if !postings.iter().any(|p| p.id == id && p.field == field) {
+                    postings.push(Posting { id, field });
+                }
+            }
+        };
+
+        for song in songs.values() {
+            add(&song.title, song.id, Field::Title);
+            for artist in &song.artists {
+                add(artist, song.id, Field::Artist);
+            }
+            add(&song.album, song.id, Field::Album);
+        }
+
+        let mut by_len: HashMap<usize, Vec<String>> = HashMap::new();
+        for term in terms.keys() {
+            by_len
+                .entry(term.chars().count())
+                .or_default()
+                .push(term.clone());
+        }
+
+        SearchIndex { terms, by_len }
+    }
+
+    /// Songs where every word of `query` matches a word in one of `fields`,
+    /// exactly, as a prefix or within a few typos. Best matches first.
+    pub fn search(&self, query: &str, fields: &[Field], songs: &SongIndex) -> Vec<SongMeta> {
+        let words = tokenize(query);
+        if words.is_empty() {
+            return Vec::new();
+        }
+
+        let mut scores: HashMap<Uuid, f32> = HashMap::new();
+        for (i, word) in words.iter().enumerate() {
+            let mut best: HashMap<Uuid, f32> = HashMap::new();
+            for (term, closeness) in self.candidates(word) {
+                for posting in &self.terms[term.as_str()] {
+                    if !fields.contains(&posting.field) {
+                        continue;
+                    }
+                    let score = closeness * posting.field.weight();
+                    let entry = best.entry(posting.id).or_insert(0.0);
+                    *entry = entry.max(score);
+                }
+            }
+
+            // every word has to match something
+            if i == 0 {
+                scores = best;
+            } else {
+                scores.retain(|id, total| match best.get(id) {
+                    Some(score) => {
+                        *total += score;
+                        true
+                    }
+                    None => false,
+                });
+            }
+        }
+
+        let phrase = words.join(" ");
+        let mut results: Vec<(f32, &SongMeta)> = scores
+            .into_iter()
+            .filter_map(|(id, score)| songs.get(&id).map(|song| (score, song)))
+            .map(|(score, song)| {
+                // whole-title matches beat songs that merely share words
+                let exact = fields.contains(&Field::Title) && normalize(&song.title) == phrase;
+                (if exact { score * 2.0 } else { score }, song)
+            })
+            .collect();
+
+        results.sort_by(|(a, x), (b, y)| b.total_cmp(a).then_with(|| x.title.cmp(&y.title)));
+        results.into_iter().map(|(_, song)| song.clone()).collect()
+    }
+
+    /// Index terms close to `word`, with how close they are from 0 to 1.
+    /// Prefixes come from a range of the ordered terms. Typos are only
+    /// looked for among terms of about the same length, and typos in a
+    /// partly typed word among longer terms with the same first letter.
+    fn candidates<'a>(&'a self, word: &str) -> HashMap<&'a String, f32> {
+        let len = word.chars().count();
+        let max_edits = match len {
+            0..=3 => 0,
+            4..=6 => 1,
+            _ => 2,
+        };
+
+        let mut found: HashMap<&String, f32> = HashMap::new();
+        let mut add = |term: &'a String, closeness: f32| {
+            let entry = found.entry(term).or_insert(0.0);
+            *entry = entry.max(closeness);
+        };
+
+        for (term, _) in self
+            .terms
+            .range::<str, _>((Bound::Included(word), Bound::Unbounded))
+            .take_while(|(term, _)| term.starts_with(word))
+        {
+            add(term, if term == word { 1.0 } else { 0.8 });
+        }
+        if max_edits == 0 {
+            return found;
+        }
+
+        for term in (len - max_edits..=len + max_edits)
+            .filter_map(|l| self.by_len.get(&l))
+            .flatten()
+        {
+            if let Some(edits) = edit_distance(word, term, max_edits) {
+                add(term, 0.6 / edits.max(1) as f32);
+            }
+        }
+
+        // a typo in a partly typed word
+        let Some(first) = word.chars().next() else {
+            return found;
+        };
+        let mut buf = [0; 4];
+        let first: &str = first.encode_utf8(&mut buf);
+        for (term, _) in self
+            .terms
+            .range::<str, _>((Bound::Included(first), Bound::Unbounded))
+            .take_while(|(term, _)| term.starts_with(first))
+        {
+            if term.chars().count() <= len {
+                continue;
+            }
+            let head: String = term.chars().take(len).collect();
+            if let Some(edits) = edit_distance(word, &head, max_edits) {
+                add(term, 0.4 / edits.max(1) as f32);
+            }
+        }
+        found
+    }
+}
+
+/// Decomposes, strips diacritics and lowercases, leaving words separated by
+/// single spaces. "Björk" becomes "bjork".
+pub fn normalize(text: &str) -> String {
+    tokenize(text).join(" ")
+}
+
+/// The words of `text`, normalized as the index stores them.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Edit distance between `a` and `b` counting adjacent swaps as one edit,
+/// or `None` once it exceeds `max`.
+fn edit_distance(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+
+    let mut prev2: Vec<usize> = Vec::new();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (prev[j] + 1).min(row[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(prev2[j - 2] + 1);
+            }
+        }
+        if row.iter().min().is_some_and(|m| *m > max) {
+            return None;
+        }
+        prev2 = std::mem::replace(&mut prev, row);
+    }
+
+    Some(prev[b.len()]).filter(|d| *d <= max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(n: u128, title: &str, artist: &str, album: &str) -> SongMeta {
+        SongMeta {
+            id: Uuid::from_u128(n),
+            title: title.to_string(),
+            artists: vec![artist.to_string()],
+            album: album.to_string(),
+            album_artist: String::new(),
+            genre: String::new(),
+            year: None,
+            disc: None,
+            track: None,
+            duration: 180,
+            path: Default::default(),
+            added: 0,
+        }
+    }
+
+    fn library() -> SongIndex {
+        [
+            song(1, "Yesterday", "The Beatles", "Help!"),
+            song(2, "Help!", "The Beatles", "Help!"),
+            song(3, "Hyperballad", "Björk", "Post"),
+            song(4, "Army of Me", "Björk", "Post"),
+            song(5, "Yesterday Once More", "Carpenters", "Now & Then"),
+            song(6, "Help Me", "Joni Mitchell", "Court and Spark"),
+        ]
+        .into_iter()
+        .map(|song| (song.id, song))
+        .collect()
+    }
+
+    fn titles(query: &str, fields: &[Field]) -> Vec<String> {
+        let songs = library();
+        SearchIndex::build(&songs)
+            .search(query, fields, &songs)
+            .into_iter()
+            .map(|song| song.title)
+            .collect()
+    }
+
+    const ALL: [Field; 3] = [Field::Title, Field::Artist, Field::Album];
+
+    #[test]
+    fn ranking() {
+        // the whole title first, then titles over albums
+        assert_eq!(titles("help", &ALL), vec!["Help!", "Help Me", "Yesterday"]);
+        assert_eq!(
+            titles("yesterday", &ALL),
+            vec!["Yesterday", "Yesterday Once More"]
+        );
+        // every word has to match
+        assert_eq!(titles("yesterday once", &ALL), vec!["Yesterday Once More"]);
+        assert_eq!(titles("post", &[Field::Title]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn typos_and_accents() {
+        assert_eq!(titles("bjork", &[Field::Artist]).len(), 2);
+        assert_eq!(titles("beatls", &[Field::Artist]).len(), 2);
+        assert_eq!(titles("yestreday", &[Field::Title]).len(), 2);
+        assert_eq!(titles("hyprballad", &[Field::Title]), vec!["Hyperballad"]);
+        // too short to guess at
+        assert!(titles("pst", &[Field::Album]).is_empty());
+    }
+
+    #[test]
+    fn prefixes() {
+        assert_eq!(titles("hyper", &ALL), vec!["Hyperballad"]);
+        assert_eq!(
+            titles("carp", &[Field::Artist]),
+            vec!["Yesterday Once More"]
+        );
+        // a typo while still typing
+        assert_eq!(titles("hypre", &[Field::Title]), vec!["Hyperballad"]);
+        assert!(titles("zzz", &ALL).is_empty());
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("bjork", "bjork", 2), Some(0));
+        assert_eq!(edit_distance("beatls", "beatles", 2), Some(1));
+        assert_eq!(edit_distance("yestreday", "yesterday", 2), Some(1));
+        assert_eq!(edit_distance("abc", "xyz", 2), None);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::{
+    helpers::{data_dir, user_key},
+    streams::CancelHandle,
+    types::*,
+};
+use anyhow::Result;
+use musicman_protocols::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// How often sessions with unsaved changes are written out. Updates come
+/// in every few seconds per client, too often to write each one.
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Held while sessions are being written, so two saves of the same file
+/// can't overlap or finish out of order.
+static SAVING: Mutex<()> = Mutex::const_new(());
+
+/// Playback state held for clients so they can pick up where they left off
+/// after reconnecting, or from another machine.
+#[derive(Default)]
+pub struct Session {
+    pub queue: Vec<Uuid>,
+    pub current_idx: usize,
+    pub position: u32,
+    pub modes: SessionModes,
+    pub clients: usize,
+    /// Connections of the attached clients, which synced streams go to.
+    pub listeners: Listeners,
+    pub sync: bool,
+    /// Cancel handles of the streams going to the session while synced,
+    /// dropped whenever its playback is replaced.
+    pub streams: Vec<CancelHandle>,
+    /// Tracks streamed to the session that haven't finished playing yet.
+    pub scheduled: Vec<Scheduled>,
+    /// Changed since it was last saved.
+    pub dirty: bool,
+}
+
+/// What's kept of a session across restarts, in `sessions.json` in its
+/// owner's data directory.
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    queue: Vec<Uuid>,
+    current_idx: usize,
+    position: u32,
+    modes: SessionModes,
+}
+
+/// A track streamed to a synced session, by when it plays.
+#[derive(Clone, Copy)]
+pub struct Scheduled {
+    pub track_id: Uuid,
+    // unix milliseconds at which the beginning of the track plays
+    pub begins_at: u64,
+    pub ends_at: Option<u64>,
+}
+
+/// Sessions by key. A logged-in user's sessions are keyed `user/name` so
+/// that users only see their own; the rest go by their bare name.
+pub type Sessions = HashMap<String, Session>;
+
+/// The key of `user`'s session called `name`. Without a login, names with
+/// a `/` would reach into a user's sessions, so there's no key for them.
+pub fn key(user: Option<&str>, name: &str) -> Option<String> {
+    match user {
+        Some(user) => Some(format!("{}/{name}", user_key(user))),
+        None if name.contains('/') => None,
+        None => Some(name.to_string()),
+    }
+}
+
+/// The user a session belongs to.
+pub fn owner(key: &str) -> Option<&str> {
+    key.split_once('/').map(|(user, _)| user)
+}
+
+/// The name a session is shown under to its user.
+pub fn display_name(key: &str) -> &str {
+    key.split_once('/').map_or(key, |(_, name)| name)
+}
+
+impl Session {
+    /// The session as sent to clients. Tracks that left the index are
+    /// dropped, keeping the current index on the same track where possible.
+    pub fn state(&self, key: &str, index: &SongIndex) -> SessionState {
+        let queue: Vec<SongMeta> = self
+            .queue
+            .iter()
+            .filter_map(|id| index.get(id).cloned())
+            .collect();
+        // the current track, or the one after it if it's gone
+        let kept_before = self.queue[..self.current_idx.min(self.queue.len())]
+            .iter()
+            .filter(|id| index.contains_key(id))
+            .count();
+
+        SessionState {
+            name: display_name(key).to_string(),
+            current_idx: kept_before.min(queue.len().saturating_sub(1)),
+            position: self.position,
+            modes: self.modes.clone(),
+            sync: self.sync,
+            queue,
+        }
+    }
+
+    pub fn update(&mut self, update: SessionUpdate) {
+        self.queue = update.queue;
+        self.current_idx = update.current_idx;
+        self.position = update.position;
+        self.modes = update.modes;
+        self.dirty = true;
+    }
+}
+
+fn sessions_file(user: Option<&str>) -> Result<PathBuf> {
+    Ok(data_dir(user)?.join("sessions.json"))
+}
+
+/// The sessions saved by every user, and by clients that don't log in.
+pub async fn load() -> Result<Sessions> {
+    let mut sessions = Sessions::new();
+    load_saved(&mut sessions, None).await?;
+    if let Ok(mut dir) = tokio::fs::read_dir(data_dir(None)?.join("users")).await {
+        while let Some(entry) = dir.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                let user = entry.file_name().to_string_lossy().to_string();
+                load_saved(&mut sessions, Some(&user)).await?;
+            }
+        }
+    }
+    tracing::info!("Loaded {} sessions.", sessions.len());
+    Ok(sessions)
+}
+
+async fn load_saved(sessions: &mut Sessions, user: Option<&str>) -> Result<()> {
+    let data = match tokio::fs::read_to_string(sessions_file(user)?).await {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let saved: HashMap<String, Saved> = serde_json::from_str(&data)?;
+    for (name, saved) in saved {
+        let Some(key) = key(user, &name) else {
+            continue;
+        };
+        let session = Session {
+            queue: saved.queue,
+            current_idx: saved.current_idx,
+            position: saved.position,
+            modes: saved.modes,
+            ..Default::default()
+        };
+        sessions.insert(key, session);
+    }
+    Ok(())
+}
+
+/// Writes out the sessions of every user who has one with unsaved changes.
+/// They're copied while `sessions` is locked and written once it's free.
+pub async fn flush(sessions: &Mutex<Sessions>) {
+    let _saving = SAVING.lock().await;
+    let mut pending: HashMap<Option<String>, HashMap<String, Saved>> = HashMap::new();
+    {
+        let mut sessions = sessions.lock().await;
+        let owners: HashSet<Option<String>> = sessions
+            .iter_mut()
+            .filter_map(|(key, session)| {
+                std::mem::take(&mut session.dirty).then(|| owner(key).map(str::to_string))
+            })
+            .collect();
+        for (key, session) in sessions.iter() {
+            let user = owner(key).map(str::to_string);
+            if !owners.contains(&user) {
+                continue;
+            }
+            let saved = Saved {
+                queue: session.queue.clone(),
+                current_idx: session.current_idx,
+                position: session.position,
+                modes: session.modes.clone(),
+            };
+            pending
+                .entry(user)
+                .or_default()
+                .insert(display_name(key).to_string(), saved);
+        }
+    }
+
+    for (user, saved) in pending {
+        if let Err(e) = save(user.as_deref(), &saved).await {
+            tracing::warn!("Could not save sessions: {e}");
+        }
+    }
+}
+
+/// Flushes the sessions every `SAVE_INTERVAL`.
+pub fn save_periodically(ctx: Arc<Context>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SAVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            flush(&ctx.sessions).await;
+        }
+    });
+}
+
+/// Writes `saved` as the sessions of `user` to their data directory.
+async fn save(user: Option<&str>, saved: &HashMap<String, Saved>) -> Result<()> {
+    let file = sessions_file(user)?;
+    if let Some(dir) = file.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    // written aside and renamed over, so a crash can't leave half a file
+    let tmp_file = file.with_extension("json.tmp");
+    tokio::fs::write(&tmp_file, serde_json::to_string_pretty(saved)?).await?;
+    tokio::fs::rename(tmp_file, file).await?;
+    Ok(())
+}
+
+pub async fn attach(
+    sessions: &mut Sessions,
+    key: &str,
+    index: &SongIndex,
+    write: &WriteSocket,
+) -> SessionState {
+    let session = sessions.entry(key.to_string()).or_default();
+    session.clients += 1;
+    session
+        .listeners
+        .lock()
+        .await
+        .push(Listener::new(write.clone()));
+    session.state(key, index)
+}
+
+pub async fn detach(sessions: &mut Sessions, key: &str, write: &WriteSocket) {
+    if let Some(session) = sessions.get_mut(key) {
+        session.clients = session.clients.saturating_sub(1);
+        session
+            .listeners
+            .lock()
+            .await
+            .retain(|listener| !Arc::ptr_eq(&listener.write, write));
+    }
+}
+
+/// The sessions of `user`.
+pub fn list(sessions: &Sessions, user: Option<&str>) -> Vec<SessionSummary> {
+    let user = user.map(user_key);
+    let mut list: Vec<SessionSummary> = sessions
+        .iter()
+        .filter(|(key, _)| owner(key) == user.as_deref())
+        .map(|(key, session)| SessionSummary {
+            name: display_name(key).to_string(),
+            len: session.queue.len(),
+            clients: session.clients,
+            sync: session.sync,
+        })
+        .collect();
+    list.sort_by(|a, b| a.name.cmp(&b.name));
+    list
+}
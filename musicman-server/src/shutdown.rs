@@ -0,0 +1,44 @@
+use crate::{sessions, types::Context};
+use std::time::Duration;
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    time::Instant,
+};
+
+/// How long running streams get to finish once the server is shutting
+/// down, before they're cancelled.
+pub const GRACE: Duration = Duration::from_secs(5);
+
+/// Resolves on the first SIGINT or SIGTERM.
+pub async fn signalled() -> anyhow::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => res?,
+        _ = terminate.recv() => {}
+    }
+    Ok(())
+}
+
+/// Waits for the connections to wind down after `ctx.shutdown` has been
+/// cancelled, then for any play still being written to the history, and
+/// saves the sessions. Gives up on connections that outlast the grace
+/// period by a second.
+pub async fn run(ctx: &Context) {
+    // synced streams only stop between chunks, and have no one to finish for
+    for session in ctx.sessions.lock().await.values_mut() {
+        session.streams.clear();
+    }
+
+    let deadline = Instant::now() + GRACE + Duration::from_secs(1);
+    while !ctx.connections.lock().await.is_empty() && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let left = ctx.connections.lock().await.len();
+    if left > 0 {
+        tracing::warn!("Closing {left} connections that didn't wind down in time.");
+    }
+
+    // an append in progress holds the lock until it's written
+    drop(ctx.history.lock().await);
+    sessions::flush(&ctx.sessions).await;
+}
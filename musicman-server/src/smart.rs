@@ -0,0 +1,69 @@
+use crate::{
+    search::{normalize, tokenize},
+    types::*,
+};
+use musicman_protocols::*;
+
+/// Whether `song` passes every filter in `query`. Tracks that were never
+/// played don't match any `played:` filter.
+pub fn matches(query: &Query, song: &SongMeta, stats: Option<&TrackStats>, now: u64) -> bool {
+    query
+        .filters
+        .iter()
+        .all(|filter| matches_filter(filter, song, stats, now))
+}
+
+/// Text is compared with accents and punctuation folded away, so
+/// `artist:bjork` matches "Björk".
+fn matches_filter(filter: &Filter, song: &SongMeta, stats: Option<&TrackStats>, now: u64) -> bool {
+    let contains = |text: &str, value: &str| normalize(text).contains(&normalize(value));
+    match filter {
+        // word by word as the search index matches them, less the typos,
+        // so `-live` doesn't rule out "Oliver"
+        Filter::Words(words) => {
+            let terms: Vec<String> = [&song.title, &song.album]
+                .into_iter()
+                .chain(&song.artists)
+                .flat_map(|text| tokenize(text))
+                .collect();
+            let words = tokenize(words);
+            !words.is_empty()
+                && words
+                    .iter()
+                    .all(|word| terms.iter().any(|term| term.starts_with(word.as_str())))
+        }
+        Filter::Not(inner) => !matches_filter(inner, song, stats, now),
+        Filter::Text { field, value } => match field {
+            TextField::Title => contains(&song.title, value),
+            TextField::Artist => song.artists.iter().any(|a| contains(a, value)),
+            TextField::Album => contains(&song.album, value),
+            TextField::Genre => contains(&song.genre, value),
+        },
+        Filter::Number { field, cmp } => {
+            let value = match field {
+                NumberField::Year => song.year.map(u64::from),
+                NumberField::Duration => Some(song.duration as u64),
+                NumberField::Plays => Some(stats.map_or(0, |s| s.plays) as u64),
+            };
+            value.is_some_and(|v| cmp.test(v))
+        }
+        Filter::Age { field, cmp } => {
+            let when = match field {
+                AgeField::Added => Some(song.added),
+                AgeField::Played => stats.and_then(|s| s.last_played),
+            };
+            when.is_some_and(|t| cmp.test(now.saturating_sub(t)))
+        }
+    }
+}
+
+/// The indexed tracks matching `query`, ordered by artist, album and title.
+pub fn evaluate(query: &Query, index: &SongIndex, stats: &StatsIndex, now: u64) -> Vec<SongMeta> {
+    let mut songs: Vec<SongMeta> = index
+        .values()
+        .filter(|song| matches(query, song, stats.get(&song.id), now))
+        .cloned()
+        .collect();
+    songs.sort_by(|a, b| (&a.artists, &a.album, &a.title).cmp(&(&b.artists, &b.album, &b.title)));
+    songs
+}
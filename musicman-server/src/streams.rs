@@ -0,0 +1,116 @@
+use musicman_protocols::StreamId;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{Instant, timeout_at},
+};
+
+/// Streams a single connection may have running at once.
+pub const MAX_STREAMS: usize = 4;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A stream ID no other stream on the server has had.
+pub fn next_id() -> StreamId {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Cancels the stream holding the matching `CancelToken`, when told to or
+/// when dropped.
+pub struct CancelHandle(watch::Sender<bool>);
+
+/// Held by a stream to find out it was cancelled.
+#[derive(Clone)]
+pub struct CancelToken(watch::Receiver<bool>);
+
+pub fn cancel_pair() -> (CancelHandle, CancelToken) {
+    let (tx, rx) = watch::channel(false);
+    (CancelHandle(tx), CancelToken(rx))
+}
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+impl CancelToken {
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow() || self.0.has_changed().is_err()
+    }
+
+    /// Resolves once the stream is cancelled, to select on while waiting.
+    pub async fn cancelled(&self) {
+        let mut rx = self.0.clone();
+        let _ = rx.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+/// A stream running as its own task.
+pub struct RunningStream {
+    pub id: StreamId,
+    pub cancel: CancelHandle,
+    pub task: JoinHandle<()>,
+}
+
+/// The streams running to one connection.
+#[derive(Default)]
+pub struct Streams {
+    running: HashMap<StreamId, RunningStream>,
+    queue_end: Arc<AtomicU64>,
+}
+
+impl Streams {
+    /// When playback of everything streamed to the client ends, in unix
+    /// milliseconds, as far as the streams have got.
+    pub fn queue_end(&self) -> Arc<AtomicU64> {
+        self.queue_end.clone()
+    }
+
+    /// Whether another stream would go over `MAX_STREAMS`. Streams that
+    /// have finished don't count.
+    pub fn is_full(&mut self) -> bool {
+        self.running.retain(|_, stream| !stream.task.is_finished());
+        self.running.len() >= MAX_STREAMS
+    }
+
+    pub fn insert(&mut self, stream: RunningStream) {
+        self.running.insert(stream.id, stream);
+    }
+
+    /// Cancels a stream and waits for its task to end, so none of it is
+    /// written after this returns.
+    pub async fn stop(&mut self, id: StreamId) {
+        if let Some(stream) = self.running.remove(&id) {
+            stream.cancel.cancel();
+            let _ = stream.task.await;
+        }
+    }
+
+    pub async fn stop_all(&mut self) {
+        for (_, stream) in self.running.drain() {
+            stream.cancel.cancel();
+            let _ = stream.task.await;
+        }
+    }
+
+    /// Lets the streams run to their end, cancelling any still going after
+    /// `within`.
+    pub async fn finish(&mut self, within: Duration) {
+        let deadline = Instant::now() + within;
+        for (_, mut stream) in self.running.drain() {
+            if timeout_at(deadline, &mut stream.task).await.is_err() {
+                stream.cancel.cancel();
+                let _ = stream.task.await;
+            }
+        }
+    }
+}
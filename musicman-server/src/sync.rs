@@ -0,0 +1,218 @@
+use crate::{
+    handlers::{Start, StreamOptions, stream_file},
+    helpers::*,
+    sessions::{Scheduled, Session, display_name, owner},
+    streams::{CancelToken, cancel_pair, next_id},
+    types::*,
+};
+use musicman_protocols::*;
+use std::{sync::Arc, time::Duration};
+use uuid::Uuid;
+
+/// How far ahead a synced track is scheduled, so every client has buffered
+/// its start before it plays.
+const START_LEAD_MS: u64 = 1500;
+/// How long before a synced track ends the next one is streamed.
+const NEXT_LEAD_MS: u64 = 15_000;
+
+/// Plays `track_id` from `position` seconds in for every client attached to
+/// the session `name`, then carries on through the session queue. Replaces
+/// whatever the session was playing.
+pub async fn play(ctx: &Arc<Context>, name: &str, track_id: Uuid, position: u32) {
+    let (cancel, token) = cancel_pair();
+    let mut sessions = ctx.sessions.lock().await;
+    let Some(session) = sessions.get_mut(name) else {
+        return;
+    };
+    session.streams = vec![cancel];
+    session.scheduled.clear();
+    let listeners = session.listeners.clone();
+    drop(sessions);
+
+    let first = Scheduled {
+        track_id,
+        begins_at: (unix_now_ms() + START_LEAD_MS).saturating_sub(position as u64 * 1000),
+        ends_at: None,
+    };
+    tokio::spawn(drive(
+        ctx.clone(),
+        name.to_string(),
+        first,
+        position,
+        listeners,
+        token,
+    ));
+}
+
+/// Stops whatever a session is streaming to its clients.
+pub fn stop(session: &mut Session) {
+    session.streams.clear();
+    session.scheduled.clear();
+}
+
+/// Streams tracks to the session back to back, each one starting when the
+/// previous one ends.
+async fn drive(
+    ctx: Arc<Context>,
+    name: String,
+    mut track: Scheduled,
+    mut position: u32,
+    listeners: Listeners,
+    cancel: CancelToken,
+) {
+    let output = Output::Session(listeners);
+    let mut prefetch = false;
+    loop {
+        schedule(&ctx, &name, track, &cancel).await;
+        let file = {
+            let library = ctx.library();
+            get_track_file(&track.track_id, library.songs()).await
+        };
+        let Ok(file) = file else {
+            tracing::warn!("Synced track {} not found.", track.track_id);
+            return;
+        };
+
+        let play = PlayEvent {
+            time: 0,
+            track_id: track.track_id,
+            client: format!("session {}", display_name(&name)),
+            user: owner(&name).map(str::to_string),
+        };
+        let opts = StreamOptions {
+            id: next_id(),
+            start: Start::Secs(position),
+            prefetch,
+            begins_at: Some(track.begins_at),
+            log: true,
+            lead_secs: ctx.config().stream.lead_secs,
+            queue_end: None,
+        };
+        let len = match stream_file(file, play, opts, &ctx, &output, &cancel).await {
+            Ok(Some(len)) => len,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::error!("Synced stream of session {name} failed. {e}");
+                return;
+            }
+        };
+        let ends_at = track.begins_at + len;
+        track.ends_at = Some(ends_at);
+        schedule(&ctx, &name, track, &cancel).await;
+
+        // Clients hold a single track ahead, so the next one can't go out
+        // before this one has started playing.
+        let starts_at = track.begins_at + position as u64 * 1000;
+        let wake = ends_at.saturating_sub(NEXT_LEAD_MS).max(starts_at);
+        let wait = Duration::from_millis(wake.saturating_sub(unix_now_ms()));
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = cancel.cancelled() => return,
+        }
+
+        let Some(next) = next_track(&ctx, &name, track.track_id).await else {
+            return;
+        };
+        track = Scheduled {
+            track_id: next,
+            begins_at: ends_at,
+            ends_at: None,
+        };
+        position = 0;
+        prefetch = true;
+    }
+}
+
+/// Records `track` in the session's schedule, forgetting tracks that have
+/// finished playing. Nothing is recorded once the stream was replaced.
+async fn schedule(ctx: &Context, name: &str, track: Scheduled, cancel: &CancelToken) {
+    let mut sessions = ctx.sessions.lock().await;
+    let Some(session) = sessions.get_mut(name) else {
+        return;
+    };
+    if cancel.is_cancelled() {
+        return;
+    }
+    let now = unix_now_ms();
+    session
+        .scheduled
+        .retain(|s| s.begins_at != track.begins_at && s.ends_at.is_none_or(|end| end > now));
+    session.scheduled.push(track);
+}
+
+/// The track after `current` in the session queue, wrapping around at the
+/// end like the client does.
+async fn next_track(ctx: &Context, name: &str, current: Uuid) -> Option<Uuid> {
+    let sessions = ctx.sessions.lock().await;
+    let session = sessions.get(name)?;
+    if !session.sync || session.queue.is_empty() {
+        return None;
+    }
+    let idx = match session.queue.get(session.current_idx) == Some(&current) {
+        true => session.current_idx,
+        false => session.queue.iter().position(|id| *id == current)?,
+    };
+    session.queue.get((idx + 1) % session.queue.len()).copied()
+}
+
+/// Brings a client that attached to a synced session mid-track up to
+/// speed: it gets the rest of the track playing now, and the one after it
+/// if that has already gone out to the others.
+pub fn catch_up(ctx: &Arc<Context>, session: &mut Session, write: &WriteSocket) {
+    let now = unix_now_ms();
+    let tracks: Vec<Scheduled> = session
+        .scheduled
+        .iter()
+        .filter(|s| s.ends_at.is_none_or(|end| end > now + START_LEAD_MS))
+        .copied()
+        .collect();
+    if tracks.is_empty() {
+        return;
+    }
+
+    let (cancel, token) = cancel_pair();
+    session.streams.push(cancel);
+    let ctx = ctx.clone();
+    let output = Output::Client(write.clone());
+    tokio::spawn(async move {
+        for (i, track) in tracks.into_iter().enumerate() {
+            // seconds already played, rounded up
+            let behind = (now + START_LEAD_MS).saturating_sub(track.begins_at);
+            let position = match i {
+                0 => behind.div_ceil(1000) as u32,
+                _ => 0,
+            };
+
+            let file = {
+                let library = ctx.library();
+                get_track_file(&track.track_id, library.songs()).await
+            };
+            let Ok(file) = file else {
+                return;
+            };
+            let play = PlayEvent {
+                time: 0,
+                track_id: track.track_id,
+                client: String::new(),
+                user: None,
+            };
+            let opts = StreamOptions {
+                id: next_id(),
+                start: Start::Secs(position),
+                prefetch: i > 0,
+                begins_at: Some(track.begins_at),
+                log: false,
+                lead_secs: ctx.config().stream.lead_secs,
+                queue_end: None,
+            };
+            match stream_file(file, play, opts, &ctx, &output, &token).await {
+                Ok(Some(_)) => {}
+                Ok(None) => return,
+                Err(e) => {
+                    tracing::error!("Catching up a synced client failed. {e}");
+                    return;
+                }
+            }
+        }
+    });
+}
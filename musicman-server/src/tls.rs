@@ -0,0 +1,41 @@
+use crate::config::TlsConfig;
+use anyhow::{Context as _, Result};
+use std::{net::SocketAddr, sync::Arc};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    },
+};
+
+/// First byte of a TLS handshake record. Requests start with a length
+/// that would have to be hundreds of megabytes to begin with it.
+const HANDSHAKE: u8 = 0x16;
+
+/// An acceptor serving the configured certificate chain and key.
+pub fn acceptor(config: &TlsConfig) -> Result<TlsAcceptor> {
+    let certs = CertificateDer::pem_file_iter(&config.cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Could not read {}", config.cert.display()))?;
+    let key = PrivateKeyDer::from_pem_file(&config.key)
+        .with_context(|| format!("Could not read {}", config.key.display()))?;
+
+    let server = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(TlsAcceptor::from(Arc::new(server)))
+}
+
+/// Whether the client opened with a TLS handshake rather than a request.
+pub async fn is_handshake(socket: &TcpStream) -> std::io::Result<bool> {
+    let mut first = [0u8; 1];
+    let n = socket.peek(&mut first).await?;
+    Ok(n == 1 && first[0] == HANDSHAKE)
+}
+
+/// Plain connections are only taken from this machine once TLS is set up.
+pub fn plain_allowed(addr: &SocketAddr) -> bool {
+    addr.ip().is_loopback()
+}
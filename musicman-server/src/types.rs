@@ -1,14 +1,153 @@
-use musicman_protocols::SongMeta;
-use std::{collections::HashMap, sync::Arc};
+use crate::{
+    config::Config,
+    history::Histories,
+    search::SearchIndex,
+    sessions::Sessions,
+    streams::{CancelToken, Streams},
+};
+use musicman_protocols::{Permission, SongMeta};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc, time::Instant};
 use tokio::{
-    net::tcp::OwnedWriteHalf,
+    io::{AsyncRead, AsyncWrite},
     sync::{Mutex, mpsc},
 };
 use uuid::Uuid;
 
 pub struct State {
-    pub current_stream_cancel: Option<mpsc::Sender<()>>,
+    pub streams: Streams,
+    pub session: Option<String>,
+    /// Name this client registered to be controlled by.
+    pub name: Option<String>,
+    /// The last track streamed to this client.
+    pub track: Option<Uuid>,
+    /// Who the client logged in as.
+    pub user: Option<User>,
+    /// Seconds of audio the client holds back from playback.
+    pub held_secs: u64,
+}
+
+pub struct User {
+    pub name: String,
+    pub permission: Permission,
 }
 
-pub type WriteSocket = Arc<Mutex<OwnedWriteHalf>>;
+/// Either half of a plain or TLS connection.
+pub type ReadSocket = Box<dyn AsyncRead + Send + Unpin>;
+pub type WriteSocket = Arc<Mutex<Box<dyn AsyncWrite + Send + Unpin>>>;
+pub type Listeners = Arc<Mutex<Vec<Listener>>>;
+
+/// A client attached to a session. Messages for it wait in its own queue,
+/// so a slow client can't hold up the rest of the session.
+pub struct Listener {
+    pub write: WriteSocket,
+    pub queue: mpsc::Sender<Arc<Vec<u8>>>,
+}
+
+/// Where a stream is sent: the client that asked for it, or every client
+/// attached to a synced session.
+pub enum Output {
+    Client(WriteSocket),
+    Session(Listeners),
+}
 pub type SongIndex = HashMap<Uuid, SongMeta>;
+
+/// The indexed songs and the search index built from them. Both are only
+/// set together so the search index always matches the songs.
+pub struct Library {
+    songs: SongIndex,
+    search: SearchIndex,
+}
+
+impl Library {
+    pub fn new(songs: SongIndex) -> Self {
+        let search = SearchIndex::build(&songs);
+        Library { songs, search }
+    }
+
+    pub fn songs(&self) -> &SongIndex {
+        &self.songs
+    }
+
+    pub fn search(&self) -> &SearchIndex {
+        &self.search
+    }
+}
+
+/// State shared by every connection.
+pub struct Context {
+    /// Swapped out whole by a rescan, so requests can keep the one they
+    /// started with without holding anything up.
+    pub library: std::sync::RwLock<Arc<Library>>,
+    pub history: Mutex<Histories>,
+    pub sessions: Mutex<Sessions>,
+    pub clients: Mutex<Clients>,
+    pub connections: Mutex<Connections>,
+    /// Swapped out whole when the config is reloaded.
+    pub config: std::sync::RwLock<Arc<Config>>,
+    pub started: Instant,
+    /// Cancelled once the server starts shutting down.
+    pub shutdown: CancelToken,
+}
+
+impl Context {
+    pub fn library(&self) -> Arc<Library> {
+        self.library.read().unwrap().clone()
+    }
+
+    pub fn set_library(&self, library: Library) {
+        *self.library.write().unwrap() = Arc::new(library);
+    }
+
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    pub fn set_config(&self, config: Config) {
+        *self.config.write().unwrap() = Arc::new(config);
+    }
+}
+
+/// A live connection, as admins see it. Sending on `kick` closes it.
+pub struct Connection {
+    pub name: Option<String>,
+    pub session: Option<String>,
+    pub track: Option<Uuid>,
+    // unix seconds
+    pub connected: u64,
+    pub kick: mpsc::Sender<()>,
+}
+
+pub type Connections = HashMap<String, Connection>;
+
+/// A client that registered a name, so others can control it.
+pub struct RemoteClient {
+    pub addr: String,
+    pub write: WriteSocket,
+}
+
+pub type Clients = HashMap<String, RemoteClient>;
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct TrackStats {
+    pub plays: u32,
+    pub last_played: Option<u64>, // unix seconds
+}
+
+pub type StatsIndex = HashMap<Uuid, TrackStats>;
+
+/// A playlist whose tracks are whatever currently match `query`.
+#[derive(Serialize, Deserialize)]
+pub struct SmartPlaylist {
+    pub title: String,
+    pub query: String,
+    #[serde(default)]
+    pub shared: bool,
+}
+
+/// Playlist files written before entries were stored as track IDs.
+#[derive(Deserialize)]
+pub struct LegacyPlaylist {
+    pub title: String,
+    pub songs: Vec<SongMeta>,
+}
@@ -0,0 +1,57 @@
+use anyhow::{Result, bail};
+use std::{
+    fs::{DirBuilder, Permissions},
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tokio::net::{UnixListener, UnixStream};
+
+static NEXT_CLIENT: AtomicU64 = AtomicU64::new(1);
+
+/// Listens on `path`, replacing a socket left behind by a server that's no
+/// longer running. `mode` sets the socket's permissions, and with them who
+/// can connect.
+pub async fn bind(path: &Path, mode: Option<u32>) -> Result<UnixListener> {
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            bail!("{} exists and isn't a socket", path.display());
+        }
+        if UnixStream::connect(path).await.is_ok() {
+            bail!("Another server is listening on {}", path.display());
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    match mode {
+        Some(mode) => bind_private(path, mode),
+        None => Ok(UnixListener::bind(path)?),
+    }
+}
+
+/// Binds in a directory only the server can reach, and moves the socket to
+/// `path` once it has `mode`, so nobody can connect before it's set.
+fn bind_private(path: &Path, mode: u32) -> Result<UnixListener> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let private = parent.join(format!(".{name}.{}", std::process::id()));
+    DirBuilder::new().mode(0o700).create(&private)?;
+
+    let socket = private.join("socket");
+    let bound = UnixListener::bind(&socket).and_then(|listener| {
+        std::fs::set_permissions(&socket, Permissions::from_mode(mode))?;
+        std::fs::rename(&socket, path)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_file(&socket);
+    let _ = std::fs::remove_dir(&private);
+    Ok(bound?)
+}
+
+/// Unix socket peers have no address, so they're told apart by number.
+pub fn client_id() -> String {
+    format!("unix:{}", NEXT_CLIENT.fetch_add(1, Ordering::Relaxed))
+}